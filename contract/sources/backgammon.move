@@ -10,6 +10,9 @@ module backgammon::backgammon {
 
     //// GAME CONSTANTS    
     const TOWERS_COUNT: u8 = 24;
+    const HOME_TOWERS_COUNT: u8 = 6;
+    // `tower_index` of `choose_move` entering a nut from the bar
+    const BAR_TOWER_INDEX: u8 = 24;
     //// PLAYER CONSTANTS
     const PLAYER_HOST: u8 = 0;
     const PLAYER_GUEST: u8 = 1;
@@ -124,8 +127,9 @@ module backgammon::backgammon {
     }
 
     /*
-     * @notice places a move at a given (x,y) coordinate on a 3x3 board
-     * @dev checks to ensure a player can make a valid move
+     * @notice moves a nut of the active player from the global tower `tower_index`, or from the bar
+     * with BAR_TOWER_INDEX, by the die at `dice_index`
+     * @dev the turn passes to the opponent once every die is played
      */
     public entry fun choose_move(player: &signer, game_addr: address, tower_index: u8, dice_index: u8) acquires Game {        
         assert!(exists<Game>(game_addr), error::not_found(EGAME_DOESNT_EXIST));
@@ -133,25 +137,32 @@ module backgammon::backgammon {
 
         assert!(!game.is_game_over, error::invalid_argument(EGAME_HAS_ALREADY_FINISHED));
         assert!(game.is_game_started, error::invalid_argument(EGAME_NOT_STARTED));
+        assert_player_turn(game, signer::address_of(player));
 
-        let host_player = option::borrow(&game.host_player);
-        let guest_player = option::borrow(&game.guest_player);                    
-        let player_addr = signer::address_of(player);
-        
-        assert!(
-            player_addr != host_player.owner || player_addr != guest_player.owner,
-            error::permission_denied(EPLAYER_NOT_IN_GAME),
-        );        
-        
-        assert!(game.active_player == PLAYER_GUEST && (player_addr != guest_player.owner) , error::permission_denied(EPLAYER_NOT_YOUR_TURN));
-        assert!(game.active_player == PLAYER_HOST && (player_addr != host_player.owner) , error::permission_denied(EPLAYER_NOT_YOUR_TURN));
-
-        if(! place_move(game, tower_index, dice_index)){
+        if(place_move(game, tower_index, dice_index)){
            event::emit(EndOfTurnEvent { game_address: game_addr, }); 
         }            
             
     }
 
+    /*
+     * @notice gives up the dice left and passes the turn, for when none of them can be played
+     * @dev the contract doesn't know the legal moves, it trusts the player that there are none
+     */
+    public entry fun pass_turn(player: &signer, game_addr: address) acquires Game {
+        assert!(exists<Game>(game_addr), error::not_found(EGAME_DOESNT_EXIST));
+        let game: &mut Game = borrow_global_mut(game_addr);
+
+        assert!(!game.is_game_over, error::invalid_argument(EGAME_HAS_ALREADY_FINISHED));
+        assert!(game.is_game_started, error::invalid_argument(EGAME_NOT_STARTED));
+        assert_player_turn(game, signer::address_of(player));
+        assert!(!vector::is_empty(&game.active_player_dices), error::invalid_argument(EINVALID_DICE_INDEX));
+
+        game.active_player_dices = vector::empty();
+        game.active_player = opponent(game.active_player);
+        event::emit(EndOfTurnEvent { game_address: game_addr, });
+    }
+
     #[view]
     public fun get_dices(game_addr: address) : vector<u8> acquires Game{          
         assert!(exists<Game>(game_addr), error::not_found(EGAME_DOESNT_EXIST));  
@@ -180,7 +191,7 @@ module backgammon::backgammon {
         let guest_player = option::borrow_mut(&mut game.guest_player);
 
         assert!(
-            player_addr != host_player.owner || player_addr != guest_player.owner,
+            player_addr == host_player.owner || player_addr == guest_player.owner,
             error::permission_denied(EPLAYER_NOT_IN_GAME)
         );
 		
@@ -199,6 +210,7 @@ module backgammon::backgammon {
 		
 	}
 
+    // Numbers the towers from the player's ace point, 0 to 5 is their home board
     fun global_to_player_tower_index(player:u8, tower_index: u8): u8{
 		if(player == PLAYER_HOST)
 			TOWERS_COUNT - tower_index - 1
@@ -207,6 +219,34 @@ module backgammon::backgammon {
 		
 	}
 
+    fun opponent(player: u8): u8 {
+        if (player == PLAYER_HOST) PLAYER_GUEST else PLAYER_HOST
+    }
+
+    fun bar_nuts(bar: &Bar, player: u8): u8 {
+        if (player == PLAYER_HOST) bar.host_nuts else bar.guest_nuts
+    }
+
+    fun set_bar_nuts(bar: &mut Bar, player: u8, nuts: u8) {
+        if (player == PLAYER_HOST) {
+            bar.host_nuts = nuts;
+        } else {
+            bar.guest_nuts = nuts;
+        }
+    }
+
+    // Aborts unless `player_addr` plays in the game and it's their turn
+    fun assert_player_turn(game: &Game, player_addr: address) {
+        let host_player = option::borrow(&game.host_player);
+        let guest_player = option::borrow(&game.guest_player);
+        assert!(
+            player_addr == host_player.owner || player_addr == guest_player.owner,
+            error::permission_denied(EPLAYER_NOT_IN_GAME),
+        );
+        let active_owner = if (game.active_player == PLAYER_HOST) host_player.owner else guest_player.owner;
+        assert!(player_addr == active_owner, error::permission_denied(EPLAYER_NOT_YOUR_TURN));
+    }
+
 	fun push_nut(game: &mut Game,player: u8,player_tower_index:u8 , count: u8){
 		let global_tower_index = player_to_global_tower_index(player,player_tower_index);
         let tower = vector::borrow_mut<Tower>(&mut game.board.towers,global_tower_index as u64);
@@ -250,7 +290,7 @@ module backgammon::backgammon {
             is_game_started: false
         };
 		
-		for(player in PLAYER_HOST..PLAYER_GUEST){            
+		for(player in PLAYER_HOST..(PLAYER_GUEST + 1)){            
             push_nut(&mut game,player,0,2);
             push_nut(&mut game,player,11,5);            
             push_nut(&mut game,player,16,3);            
@@ -265,14 +305,10 @@ module backgammon::backgammon {
         assert!(exists<Game>(game_addr), error::not_found(EGAME_DOESNT_EXIST));
         let game: &mut Game = borrow_global_mut(game_addr);
 
+        assert!(!game.is_game_over, error::invalid_argument(EGAME_HAS_ALREADY_FINISHED));
         assert!(game.is_game_started, error::invalid_argument(EGAME_NOT_STARTED));
         assert!(vector::is_empty(&mut game.active_player_dices),error::already_exists(EGAME_ROLL_ARRAY_IS_NOT_EMPTY)); 
-
-		let player_addr = signer::address_of(&player);
-        let host_player = option::borrow_mut(&mut game.host_player);
-        let guest_player = option::borrow_mut(&mut game.guest_player);
-        assert!(game.active_player == PLAYER_HOST && host_player.owner == player_addr, error::not_found(EPLAYER_NOT_YOUR_TURN));
-        assert!(game.active_player == PLAYER_GUEST && guest_player.owner == player_addr, error::not_found(EPLAYER_NOT_YOUR_TURN));
+        assert_player_turn(game, signer::address_of(&player));
         
         let roll1 = randomness::u8_range(0, 6);	        
         let roll2 = randomness::u8_range(0, 6);	   
@@ -312,34 +348,61 @@ module backgammon::backgammon {
     }
 
     /*
-     * @notice place move 
+     * @notice moves a nut of the active player, hitting a lone opponent nut onto the bar
+     * @dev returns true when it was the last die and the turn passed to the opponent
      */
     fun place_move(game: &mut Game, tower_index: u8, dice_index: u8) : bool{
-        // validate game state
-        
-                
-        let tower = vector::borrow_mut(&mut game.board.towers, tower_index as u64);        
-
-        tower.nuts = tower.nuts - 1;
-        let dices: &vector<u8> = &game.active_player_dices;
-
-        let dice_num = *vector::borrow(dices,dice_index as u64);
-        {
+        assert!(dice_is_valid(game,dice_index) ,error::invalid_argument(EINVALID_DICE_INDEX));
+        let player = game.active_player;
+        // Dice are drawn from 0 to 5
+        let pips = *vector::borrow(&game.active_player_dices,dice_index as u64) + 1;
+        let player_bar_nuts = bar_nuts(&game.board.bar, player);
+
+        let dest_player_tower_index: u8;
+        if (tower_index == BAR_TOWER_INDEX) {
+            assert!(player_bar_nuts > 0, error::invalid_argument(EINVALID_TOWER_INDEX));
+            set_bar_nuts(&mut game.board.bar, player, player_bar_nuts - 1);
+            dest_player_tower_index = pips - 1;
+        } else {
+            // Nuts on the bar enter before any other moves
+            assert!(player_bar_nuts == 0, error::invalid_argument(EINVALID_MOVE));
+            assert!(tower_index_is_valid(tower_index) ,error::invalid_argument(EINVALID_TOWER_INDEX));
             let source_tower = vector::borrow_mut(&mut game.board.towers,tower_index as u64);
+            assert!(source_tower.owner == player && source_tower.nuts > 0, error::invalid_argument(EINVALID_TOWER_PLAYER));
             source_tower.nuts = source_tower.nuts - 1;
+            // Numbering in the direction of travel is its own inverse
+            dest_player_tower_index = player_to_global_tower_index(player, tower_index) + pips;
+            // Moving past the last tower is bearing off, see `bear_off`
+            assert!(dest_player_tower_index < TOWERS_COUNT, error::invalid_argument(EINVALID_DICE_NUM));
         };
-        
-        {
-            let dest_tower = vector::borrow_mut(&mut game.board.towers,(tower_index + dice_num) as u64);  
-            dest_tower.nuts = dest_tower.nuts + 1;
-        };        
 
-        let dices: &mut vector<u8> = &mut game.active_player_dices;        
+        let dest_tower = vector::borrow_mut(&mut game.board.towers,player_to_global_tower_index(player, dest_player_tower_index) as u64);
+        let hit = dest_tower.nuts > 0 && dest_tower.owner != player;
+        if (hit) {
+            // Two opponent nuts or more hold the tower
+            assert!(dest_tower.nuts == 1, error::invalid_argument(EINVALID_MOVE));
+            dest_tower.nuts = 0;
+        };
+        dest_tower.nuts = dest_tower.nuts + 1;
+        dest_tower.owner = player;
+        if (hit) {
+            let hit_player = opponent(player);
+            let hit_bar_nuts = bar_nuts(&game.board.bar, hit_player);
+            set_bar_nuts(&mut game.board.bar, hit_player, hit_bar_nuts + 1);
+        };
 
-        vector::remove(dices,dice_index as u64); 
+        use_dice(game, dice_index)
+    }
 
-        vector::length(dices) == 0        
-    }	
+    // Removes a played die, passing the turn once every die is played. True when it passed
+    fun use_dice(game: &mut Game, dice_index: u8): bool {
+        vector::remove(&mut game.active_player_dices,dice_index as u64);
+        if (!vector::is_empty(&game.active_player_dices)) {
+            return false
+        };
+        game.active_player = opponent(game.active_player);
+        true
+    }
 
     fun can_bear_off(game: &mut Game): bool {
         let bar = &game.board.bar;
@@ -357,7 +420,8 @@ module backgammon::backgammon {
         let towers = & game.board.towers;
         
             
-        for (player_tower_index in 0..18){        
+        // Every nut has to be home, on the towers before HOME_TOWERS_COUNT counting from the ace point
+        for (player_tower_index in HOME_TOWERS_COUNT..TOWERS_COUNT){        
             let global_tower_index = global_to_player_tower_index(game.active_player,player_tower_index as u8);
             let tower = vector::borrow(towers,global_tower_index as u64);
             if(tower.owner == game.active_player && tower.nuts>0){
@@ -382,27 +446,51 @@ module backgammon::backgammon {
     }
 
     /*
-	* player can bear off the 
+	* player can bear off a nut from `player_tower_index`, counted from their ace point, once all their nuts are home.
+	* The die must match the tower, or be higher with no nut on a tower further from the ace point
 	*/
-	public entry fun bear_off(game_addr: address, player_tower_index: u8,dice_index:u8) acquires Game {
+	public entry fun bear_off(player: &signer, game_addr: address, player_tower_index: u8,dice_index:u8) acquires Game {
         assert!(exists<Game>(game_addr), error::not_found(EGAME_DOESNT_EXIST));
         let game = borrow_global_mut(game_addr);         
+        assert!(!game.is_game_over, error::invalid_argument(EGAME_HAS_ALREADY_FINISHED));
+        assert!(game.is_game_started, error::invalid_argument(EGAME_NOT_STARTED));
+        assert_player_turn(game, signer::address_of(player));
         assert!(can_bear_off(game), error::invalid_argument(EBEAR_OFF_ERROR_NUTS_OUT_OF_HOME));
         assert!(dice_is_valid(game,dice_index) ,error::invalid_argument(EINVALID_DICE_INDEX));
-        assert!(tower_index_is_valid(player_tower_index) ,error::invalid_argument(EINVALID_TOWER_INDEX));
-        assert!(game.is_game_started, error::invalid_argument(EGAME_NOT_STARTED));
+        assert!(player_tower_index < HOME_TOWERS_COUNT ,error::invalid_argument(EINVALID_TOWER_INDEX));
         
-        let dices: &vector<u8> = &game.active_player_dices;        
-
-        let dice_num = *vector::borrow(dices,dice_index as u64);
-
-        assert!(dice_num + player_tower_index < TOWERS_COUNT ,error::invalid_argument(EINVALID_DICE_NUM));
-        let global_tower_index = global_to_player_tower_index(game.active_player,player_tower_index);
+        let active_player = game.active_player;
+        // Dice are drawn from 0 to 5
+        let pips = *vector::borrow(&game.active_player_dices,dice_index as u64) + 1;
+        assert!(
+            pips == player_tower_index + 1 || (pips > player_tower_index + 1 && !has_nuts_above(game, player_tower_index)),
+            error::invalid_argument(EINVALID_DICE_NUM)
+        );
+        // Numbering from the ace point is its own inverse
+        let global_tower_index = global_to_player_tower_index(active_player,player_tower_index);
 
         let tower = vector::borrow_mut(&mut game.board.towers,global_tower_index as u64);         
         
-        assert!(tower.owner != game.active_player ,error::invalid_argument(EINVALID_TOWER_PLAYER));
+        assert!(tower.owner == active_player && tower.nuts > 0 ,error::invalid_argument(EINVALID_TOWER_PLAYER));
         tower.nuts = tower.nuts - 1;
+
+        if (check_player_win(game)) {
+            game.is_game_over = true;
+            event::emit(GameOverEvent { game_address: game_addr, is_game_over: true, });
+        } else if (use_dice(game, dice_index)) {
+            event::emit(EndOfTurnEvent { game_address: game_addr, });
+        }
+    }
+
+    // Whether the active player has a nut on a home tower further from the ace point than `player_tower_index`
+    fun has_nuts_above(game: &Game, player_tower_index: u8): bool {
+        for (higher in (player_tower_index + 1)..HOME_TOWERS_COUNT) {
+            let tower = vector::borrow(&game.board.towers,global_to_player_tower_index(game.active_player,higher) as u64);
+            if (tower.owner == game.active_player && tower.nuts > 0) {
+                return true
+            }
+        };
+        false
     }
 
     /*
@@ -429,6 +517,22 @@ module backgammon::backgammon {
 		true		
     }    
 
+    #[test] // OK
+    fun test_initial_board_nuts() {
+        let game = initalize_game();
+        let host_nuts: u8 = 0;
+        let guest_nuts: u8 = 0;
+        for (tower_index in 0..TOWERS_COUNT) {
+            let tower = vector::borrow(&game.board.towers, tower_index as u64);
+            if (tower.owner == PLAYER_HOST) {
+                host_nuts = host_nuts + tower.nuts;
+            } else {
+                guest_nuts = guest_nuts + tower.nuts;
+            };
+        };
+        assert!(host_nuts == 15 && guest_nuts == 15, 0);
+    }
+
     #[test(game_host = @0xC0FFEE)] // OK
     #[expected_failure]
     fun test_duplicate_game_failure(game_host: &signer) {
@@ -442,4 +546,91 @@ module backgammon::backgammon {
         let game_addr = signer::address_of(game_host);
         join_game(game_guest,game_addr);                 
     }   
+
+    #[test_only]
+    fun set_tower(game: &mut Game, tower_index: u8, owner: u8, nuts: u8) {
+        let tower = vector::borrow_mut(&mut game.board.towers, tower_index as u64);
+        tower.owner = owner;
+        tower.nuts = nuts;
+    }
+
+    #[test]
+    fun test_moves_hit_and_enter() {
+        let game = initalize_game();
+        game.is_game_started = true;
+        set_tower(&mut game, 4, PLAYER_GUEST, 1);
+        // A 3 and a 1, as the dice are drawn
+        game.active_player_dices = vector[2, 0];
+
+        // The host runs a back nut from tower 0 to 3, then hits the guest's lone nut on 4
+        assert!(!place_move(&mut game, 0, 0), 0);
+        assert!(place_move(&mut game, 3, 0), 1);
+        let tower = vector::borrow(&game.board.towers, 0);
+        assert!(tower.nuts == 1, 2);
+        let tower = vector::borrow(&game.board.towers, 4);
+        assert!(tower.owner == PLAYER_HOST && tower.nuts == 1, 3);
+        assert!(game.board.bar.guest_nuts == 1, 4);
+        assert!(game.active_player == PLAYER_GUEST, 5);
+
+        // The guest enters with a 3 on the host's third tower from the end
+        game.active_player_dices = vector[2, 5];
+        assert!(!place_move(&mut game, BAR_TOWER_INDEX, 0), 6);
+        assert!(game.board.bar.guest_nuts == 0, 7);
+        let tower = vector::borrow(&game.board.towers, 21);
+        assert!(tower.owner == PLAYER_GUEST && tower.nuts == 1, 8);
+    }
+
+    #[test]
+    #[expected_failure(abort_code = 0x10000, location = Self)]
+    fun test_held_tower_blocks_moves() {
+        let game = initalize_game();
+        game.active_player_dices = vector[4, 0];
+        // The guest holds tower 5 with five nuts
+        place_move(&mut game, 0, 0);
+    }
+
+    #[test]
+    #[expected_failure(abort_code = 0x10000, location = Self)]
+    fun test_bar_enters_first() {
+        let game = initalize_game();
+        game.board.bar.host_nuts = 1;
+        game.active_player_dices = vector[0, 1];
+        place_move(&mut game, 0, 0);
+    }
+
+    #[test(game_host = @0xC0FFF1, game_guest = @0xC0FFF2)]
+    fun test_bear_off_wins(game_host: &signer, game_guest: &signer) acquires Game {
+        create_game(game_host);
+        let game_addr = signer::address_of(game_host);
+        join_game(game_guest, game_addr);
+        {
+            let game = borrow_global_mut<Game>(game_addr);
+            for (tower_index in 0..TOWERS_COUNT) {
+                set_tower(game, tower_index, PLAYER_HOST, 0);
+            };
+            // The host's last nuts are on their ace and 2 points, a 6 and a 2 bear both off
+            set_tower(game, 23, PLAYER_HOST, 1);
+            set_tower(game, 22, PLAYER_HOST, 1);
+            set_tower(game, 0, PLAYER_GUEST, 15);
+            game.active_player_dices = vector[5, 1];
+        };
+
+        bear_off(game_host, game_addr, 1, 0);
+        assert!(!borrow_global<Game>(game_addr).is_game_over, 0);
+        bear_off(game_host, game_addr, 0, 0);
+        assert!(borrow_global<Game>(game_addr).is_game_over, 1);
+    }
+
+    #[test(game_host = @0xC0FFF3, game_guest = @0xC0FFF4)]
+    #[expected_failure(abort_code = 0x5000F, location = Self)]
+    fun test_moves_out_of_turn_fail(game_host: &signer, game_guest: &signer) acquires Game {
+        create_game(game_host);
+        let game_addr = signer::address_of(game_host);
+        join_game(game_guest, game_addr);
+        {
+            let game = borrow_global_mut<Game>(game_addr);
+            game.active_player_dices = vector[0, 1];
+        };
+        choose_move(game_guest, game_addr, 23, 0);
+    }
 }
\ No newline at end of file
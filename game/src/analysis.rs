@@ -0,0 +1,409 @@
+use std::fmt;
+
+use crate::bot::Evaluator;
+use crate::cube::Cube;
+use crate::model::Action;
+use crate::search::{Search, SearchConfig};
+use crate::utils::{opponent, roll_to_dice, Board, Turn};
+
+// Equity lost, per cube unit, from which a decision stops being fine
+const DOUBTFUL_LOSS: f32 = 0.04;
+const ERROR_LOSS: f32 = 0.08;
+const BLUNDER_LOSS: f32 = 0.16;
+// Share of the cube's full value a player can really use, Janowski's x for money play
+const CUBE_EFFICIENCY: f32 = 0.68;
+// Winning chances at which a live cube is taken and cashed
+const TAKE_POINT: f32 = 0.2;
+const CASH_POINT: f32 = 0.8;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rating {
+    Fine,
+    Doubtful,
+    Error,
+    Blunder
+}
+
+impl Rating {
+    pub fn from_loss(equity_loss: f32) -> Rating {
+        if equity_loss >= BLUNDER_LOSS {
+            Rating::Blunder
+        } else if equity_loss >= ERROR_LOSS {
+            Rating::Error
+        } else if equity_loss >= DOUBTFUL_LOSS {
+            Rating::Doubtful
+        } else {
+            Rating::Fine
+        }
+    }
+
+    // Annotation marks as written after a move
+    pub fn mark(&self) -> &'static str {
+        match self {
+            Rating::Fine => "",
+            Rating::Doubtful => "?!",
+            Rating::Error => "?",
+            Rating::Blunder => "??"
+        }
+    }
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Rating::Fine => "fine",
+            Rating::Doubtful => "doubtful",
+            Rating::Error => "error",
+            Rating::Blunder => "blunder"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum DecisionKind {
+    Checker{
+        roll: [u8;2],
+        played: Turn,
+        best: Turn
+    },
+    // Rolled while doubling was better
+    NoDouble,
+    Double,
+    Take,
+    Drop
+}
+
+#[derive(Clone, Debug)]
+pub struct Decision {
+    pub player: usize,
+    // Index of the action in the game history
+    pub action: usize,
+    // Board the decision was made on
+    pub board: Board,
+    pub kind: DecisionKind,
+    // Equity given up against the best choice, per cube unit
+    pub equity_loss: f32,
+    pub rating: Rating,
+    // How much better than an average roll the dice were, for checker plays
+    pub luck: Option<f32>
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PlayerSummary {
+    pub decisions: usize,
+    pub equity_lost: f32,
+    // Sum of the luck of every roll, including forced ones
+    pub luck: f32,
+    pub doubtful: usize,
+    pub errors: usize,
+    pub blunders: usize
+}
+
+impl PlayerSummary {
+    // Thousandths of a point lost per decision
+    pub fn error_rate(&self) -> f32 {
+        if self.decisions == 0 {
+            0.
+        } else {
+            1000. * self.equity_lost / self.decisions as f32
+        }
+    }
+
+    fn add(&mut self, decision: &Decision) {
+        self.decisions += 1;
+        self.equity_lost += decision.equity_loss;
+        match decision.rating {
+            Rating::Fine => (),
+            Rating::Doubtful => self.doubtful += 1,
+            Rating::Error => self.errors += 1,
+            Rating::Blunder => self.blunders += 1
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Analysis {
+    pub decisions: Vec<Decision>,
+    pub players: [PlayerSummary;2]
+}
+
+// Cube decisions follow Janowski's money game model without gammons: the cubeful equity lies between
+// the dead cube equity of the winning chances and the equity with a fully live cube
+// https://bkgm.com/articles/Janowski/cubeformulae.pdf
+struct CubeEquities {
+    no_double: f32,
+    double_take: f32,
+    double_pass: f32
+}
+
+impl CubeEquities {
+    // Equities per current cube unit for `player` on roll, from their cubeless equity
+    fn new(equity_on_roll: f32, cube: &Cube, player: usize) -> CubeEquities {
+        let winning_chance = ((equity_on_roll + 1.) / 2.).clamp(0., 1.);
+        let (low, high) = match cube.owner {
+            None => (TAKE_POINT, CASH_POINT),
+            Some(owner) if owner == player => (0., CASH_POINT),
+            Some(_) => (TAKE_POINT, 1.)
+        };
+        CubeEquities{
+            no_double: cubeful_equity(winning_chance, low, high),
+            // After the take the opponent owns the cube at twice the value
+            double_take: 2. * cubeful_equity(winning_chance, TAKE_POINT, 1.),
+            double_pass: 1.
+        }
+    }
+
+    // The doubler gets the worse of take and pass, the opponent picks
+    fn double(&self) -> f32 {
+        self.double_take.min(self.double_pass)
+    }
+
+    // Equities of taking and passing for the opponent, per current cube unit
+    fn answers(&self) -> (f32, f32) {
+        (-self.double_take, -self.double_pass)
+    }
+
+    fn best_for_doubler(&self) -> f32 {
+        self.no_double.max(self.double())
+    }
+}
+
+// The live cube equity goes from -1 at `low` winning chances to 1 at `high`
+fn cubeful_equity(winning_chance: f32, low: f32, high: f32) -> f32 {
+    let dead = 2. * winning_chance - 1.;
+    let live = (2. * (winning_chance - low) / (high - low) - 1.).clamp(-1., 1.);
+    (1. - CUBE_EFFICIENCY) * dead + CUBE_EFFICIENCY * live
+}
+
+// Replays a game's history through the evaluator, rating every decision
+pub fn analyze(history: &[Action], evaluator: &dyn Evaluator, config: SearchConfig) -> Analysis {
+    let mut search = Search::new(evaluator, config);
+    // Luck compares the roll with the average of all 21 at the static level, which keeps it cheap
+    let mut luck_search = Search::new(evaluator, SearchConfig::ply(0));
+    let mut analysis = Analysis::default();
+    // The board and equities of a double waiting for its answer
+    let mut pending_double: Option<(Board, CubeEquities)> = None;
+
+    for (action, entry) in history.iter().enumerate() {
+        let decision = match entry {
+            Action::Turn{player, roll, board, cube, moves} => {
+                let player = *player;
+                let dice = roll_to_dice(*roll);
+                let played = Turn{
+                    moves: moves.clone()
+                };
+
+                // After a take the cube belongs to the opponent, so this only looks at rolls without a double
+                if cube.can_double(player) {
+                    let equities = CubeEquities::new(search.evaluate_on_roll(board, player), cube, player);
+                    let equity_loss = equities.best_for_doubler() - equities.no_double;
+                    // Passing up a double by less than a doubtful margin is within the evaluator's noise
+                    if Rating::from_loss(equity_loss) != Rating::Fine {
+                        push(&mut analysis, Decision{
+                            player,
+                            action,
+                            board: board.clone(),
+                            kind: DecisionKind::NoDouble,
+                            equity_loss,
+                            rating: Rating::from_loss(equity_loss),
+                            luck: None
+                        });
+                    }
+                }
+                let luck = luck_search.rank_turns(board, player, &dice).first().map_or(0., |(_, equity)| *equity)
+                    + luck_search.evaluate(board, opponent(player), 1);
+                analysis.players[player].luck += luck;
+
+                let ranked = search.rank_turns(board, player, &dice);
+                // Forced moves aren't decisions, only their luck counts
+                if ranked.len() < 2 {
+                    continue;
+                }
+                let mut after = board.clone();
+                after.apply_turn(player, &played);
+                let played_equity = search.evaluate(&after, player, config.depth);
+                let (best, best_equity) = ranked.into_iter().next().unwrap();
+                let (best, best_equity) = if played_equity > best_equity {
+                    (played.clone(), played_equity)
+                } else {
+                    (best, best_equity)
+                };
+                let equity_loss = best_equity - played_equity;
+                Decision{
+                    player,
+                    action,
+                    board: board.clone(),
+                    kind: DecisionKind::Checker{
+                        roll: *roll,
+                        played,
+                        best
+                    },
+                    equity_loss,
+                    rating: Rating::from_loss(equity_loss),
+                    luck: Some(luck)
+                }
+            },
+            Action::Double{player, board, cube} => {
+                let equities = CubeEquities::new(search.evaluate_on_roll(board, *player), cube, *player);
+                let equity_loss = equities.best_for_doubler() - equities.double();
+                let decision = Decision{
+                    player: *player,
+                    action,
+                    board: board.clone(),
+                    kind: DecisionKind::Double,
+                    equity_loss,
+                    rating: Rating::from_loss(equity_loss),
+                    luck: None
+                };
+                pending_double = Some((board.clone(), equities));
+                decision
+            },
+            Action::Take{player} | Action::Drop{player} => {
+                let Some((board, equities)) = pending_double.take() else {
+                    continue;
+                };
+                let is_take = matches!(entry, Action::Take{..});
+                let (take, pass) = equities.answers();
+                let chosen = if is_take { take } else { pass };
+                let equity_loss = take.max(pass) - chosen;
+                Decision{
+                    player: *player,
+                    action,
+                    board,
+                    kind: if is_take { DecisionKind::Take } else { DecisionKind::Drop },
+                    equity_loss,
+                    rating: Rating::from_loss(equity_loss),
+                    luck: None
+                }
+            }
+        };
+        push(&mut analysis, decision);
+    }
+    analysis
+}
+
+fn push(analysis: &mut Analysis, decision: Decision) {
+    analysis.players[decision.player].add(&decision);
+    analysis.decisions.push(decision);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::HeuristicEvaluator;
+    use crate::model::GameModel;
+    use crate::utils::{initialize, player_to_global_tower_index, BorneOff, Move, Tower, OFF_INDEX, PLAYER_GUEST, PLAYER_HOST, TOWERS_COUNT};
+
+    // The host with a nut on each of `host_points` against the guest's last nut on their ace point
+    fn race(host_points: &[usize]) -> Board {
+        let mut board = Board::empty();
+        for point in host_points {
+            board.towers[player_to_global_tower_index(PLAYER_HOST, TOWERS_COUNT - point)] = Tower{ nuts: 1, owner: PLAYER_HOST as u8 };
+        }
+        board.towers[player_to_global_tower_index(PLAYER_GUEST, TOWERS_COUNT - 1)] = Tower{ nuts: 1, owner: PLAYER_GUEST as u8 };
+        board.borne_off = BorneOff{
+            host_nuts: 15 - host_points.len() as u8,
+            guest_nuts: 14
+        };
+        board.rehash();
+        board
+    }
+
+    fn analyze_history(history: &[Action]) -> Analysis {
+        analyze(history, &HeuristicEvaluator::default(), SearchConfig::ply(1))
+    }
+
+    #[test]
+    fn missing_a_winning_bear_off_is_a_blunder() {
+        let board = race(&[2, 1]);
+        let from = |point: usize| player_to_global_tower_index(PLAYER_HOST, TOWERS_COUNT - point);
+        // 2/1 1/off leaves a nut for the guest to outrace
+        let history = [Action::Turn{
+            player: PLAYER_HOST,
+            roll: [2, 1],
+            board: board.clone(),
+            cube: Cube{ enabled: false, ..Cube::default() },
+            moves: vec![
+                Move{ from: from(2), to: from(1), die: 1, hit: false },
+                Move{ from: from(1), to: OFF_INDEX, die: 2, hit: false }
+            ]
+        }];
+        let analysis = analyze_history(&history);
+
+        assert_eq!(analysis.decisions.len(), 1);
+        let decision = &analysis.decisions[0];
+        assert_eq!(decision.board, board);
+        assert_eq!(decision.rating, Rating::Blunder);
+        assert_eq!(decision.equity_loss, 2.);
+        let DecisionKind::Checker{ best, .. } = &decision.kind else {
+            panic!("{:?} isn't a checker play", decision.kind);
+        };
+        assert!(best.moves.iter().all(|mv| mv.to == OFF_INDEX));
+
+        let host = analysis.players[PLAYER_HOST];
+        assert_eq!((host.decisions, host.blunders, host.errors), (1, 1, 0));
+        assert_eq!(host.equity_lost, 2.);
+        assert_eq!(analysis.players[PLAYER_GUEST].decisions, 0);
+    }
+
+    #[test]
+    fn taking_a_lost_game_is_a_blunder() {
+        // The host bears off with any roll, the guest should pass
+        let board = race(&[1]);
+        let history = [
+            Action::Double{ player: PLAYER_HOST, board: board.clone(), cube: Cube::default() },
+            Action::Take{ player: PLAYER_GUEST }
+        ];
+        let analysis = analyze_history(&history);
+
+        assert_eq!(analysis.decisions.len(), 2);
+        let take = &analysis.decisions[1];
+        assert!(matches!(take.kind, DecisionKind::Take));
+        assert_eq!(take.player, PLAYER_GUEST);
+        assert_eq!(take.board, board);
+        // Taking plays for -2 against -1 for passing
+        assert_eq!(take.equity_loss, 1.);
+        assert_eq!(take.rating, Rating::Blunder);
+
+        // Doubling out a sure win is right
+        assert_eq!(analysis.decisions[0].rating, Rating::Fine);
+        let guest = analysis.players[PLAYER_GUEST];
+        assert_eq!((guest.decisions, guest.blunders), (1, 1));
+        assert_eq!(guest.equity_lost, take.equity_loss);
+        assert_eq!(analysis.players[PLAYER_HOST].blunders, 0);
+    }
+
+    #[test]
+    fn passing_an_early_double_is_a_blunder() {
+        let board = initialize();
+        let history = [
+            Action::Double{ player: PLAYER_HOST, board: board.clone(), cube: Cube::default() },
+            Action::Drop{ player: PLAYER_GUEST }
+        ];
+        let analysis = analyze_history(&history);
+
+        let drop = &analysis.decisions[1];
+        assert!(matches!(drop.kind, DecisionKind::Drop));
+        assert_eq!(drop.board, board);
+        assert!(drop.equity_loss > BLUNDER_LOSS, "dropping lost {}", drop.equity_loss);
+        assert_eq!(drop.rating, Rating::Blunder);
+        assert_eq!(analysis.players[PLAYER_GUEST].blunders, 1);
+
+        // Doubling at the start is a mistake of its own, charged to the host
+        assert!(analysis.decisions[0].equity_loss > 0.);
+        assert_eq!(analysis.players[PLAYER_HOST].decisions, 1);
+    }
+
+    #[test]
+    fn small_missed_doubles_are_not_recorded() {
+        let board = initialize();
+        let mut model = GameModel::new();
+        model.roll_dice([3, 1]).unwrap();
+        let turn = board.legal_turns(PLAYER_HOST, &[3, 1]).remove(0);
+        model.play_turn(&turn).unwrap();
+
+        let analysis = analyze_history(&model.history);
+        assert!(analysis.decisions.iter().all(|decision| !matches!(decision.kind, DecisionKind::NoDouble)));
+    }
+}
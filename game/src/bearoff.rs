@@ -0,0 +1,449 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use once_cell::sync::Lazy;
+
+use crate::nn::Probabilities;
+use crate::search::{roll_probability, ROLLS};
+use crate::utils::{opponent, player_to_global_tower_index, roll_to_dice, to_player_tower_index, Board, HOME_TOWER_INDEX, NUTS_PER_PLAYER, PLAYER_GUEST, PLAYER_HOST, TOWERS_COUNT};
+
+// Bearoff databases for the no-contact endgame, once both players have every nut in their home board.
+// The one-sided table has, for every way to spread up to 15 nuts over the 6 home points, the chances of
+// needing each number of rolls to bear them all off, and to bear off the first one (for gammons), when
+// playing to need the fewest rolls on average. Races are then decided by comparing the two distributions.
+// The two-sided table has the exact winning chances of the player on roll for every pair of positions
+// with few nuts, where racing for the fewest rolls on average isn't always the best play
+
+pub const BEAROFF_PATH: &str = "assets/bearoff/bearoff.bin";
+const BEAROFF_MAGIC: &[u8;4] = b"BGBO";
+const BEAROFF_VERSION: u32 = 1;
+pub const HOME_POINTS: usize = TOWERS_COUNT - HOME_TOWER_INDEX;
+pub const ONE_SIDED_NUTS: usize = NUTS_PER_PLAYER as usize;
+pub const TWO_SIDED_NUTS: usize = 6;
+// 15 nuts on the six point are 90 pips, and every roll bears off at least 3 of them
+const MAX_ROLLS: usize = 32;
+// Chances are stored as u16 fractions of this
+const PROBABILITY_SCALE: f32 = u16::MAX as f32;
+
+// Nuts on each home point of one player, the ace point first
+pub type Position = [u8;HOME_POINTS];
+
+// The database the evaluators use, read once from `BEAROFF_PATH` when it's there
+pub static BEAROFF: Lazy<Option<BearoffDatabase>> = Lazy::new(|| BearoffDatabase::load(BEAROFF_PATH).ok());
+
+// Equity for `player` right after they moved, when the position is a no-contact bearoff and the database is around
+pub fn bearoff_equity(board: &Board, player: usize) -> Option<f32> {
+    BEAROFF.as_ref()?.probabilities(board, player).map(|probabilities| probabilities.equity())
+}
+
+// Chances of needing exactly `start + i` rolls, for each i
+#[derive(Clone, Debug, Default)]
+struct Distribution {
+    start: usize,
+    probabilities: Vec<f32>
+}
+
+impl Distribution {
+    fn from_rolls(rolls: &[f64;MAX_ROLLS]) -> Distribution {
+        let quantized: Vec<f32> = rolls.iter().map(|chance| (*chance as f32 * PROBABILITY_SCALE).round() / PROBABILITY_SCALE).collect();
+        let Some(start) = quantized.iter().position(|chance| *chance > 0.) else {
+            return Distribution::default();
+        };
+        let end = quantized.iter().rposition(|chance| *chance > 0.).unwrap() + 1;
+        Distribution{
+            start,
+            probabilities: quantized[start..end].to_vec()
+        }
+    }
+
+    fn at_least(&self, rolls: usize) -> f32 {
+        let skipped = rolls.saturating_sub(self.start).min(self.probabilities.len());
+        self.probabilities[skipped..].iter().sum()
+    }
+
+    fn rolls(&self) -> impl Iterator<Item = (usize, f32)> + '_ {
+        self.probabilities.iter().enumerate().map(|(index, chance)| (self.start + index, *chance))
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct OneSided {
+    // Rolls to bear off every nut
+    off: Distribution,
+    // Rolls to bear off the first nut
+    first_off: Distribution
+}
+
+pub struct BearoffDatabase {
+    one_sided: Vec<OneSided>,
+    // Winning chances of the player on roll, indexed by their position then the opponent's
+    two_sided: Vec<f32>
+}
+
+impl BearoffDatabase {
+    // Solves both tables by working up from the smallest pip counts, which takes a while
+    pub fn generate() -> BearoffDatabase {
+        BearoffDatabase{
+            one_sided: generate_one_sided(),
+            two_sided: generate_two_sided()
+        }
+    }
+
+    /*
+     * File layout: magic, then version, home points, one-sided and two-sided nuts as little endian u32.
+     * Each one-sided position follows in index order with its two distributions, each a u8 first roll
+     * count, a u8 length and that many u16 chances. Then a u16 chance for every two-sided pair.
+     * Chances are little endian fractions of 65535
+     */
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut bytes: Vec<u8> = BEAROFF_MAGIC.to_vec();
+        for header in [BEAROFF_VERSION, HOME_POINTS as u32, ONE_SIDED_NUTS as u32, TWO_SIDED_NUTS as u32] {
+            bytes.extend_from_slice(&header.to_le_bytes());
+        }
+        let quantize = |chance: f32| ((chance * PROBABILITY_SCALE).round() as u16).to_le_bytes();
+        for position in self.one_sided.iter() {
+            for distribution in [&position.off, &position.first_off] {
+                bytes.push(distribution.start as u8);
+                bytes.push(distribution.probabilities.len() as u8);
+                for chance in distribution.probabilities.iter() {
+                    bytes.extend_from_slice(&quantize(*chance));
+                }
+            }
+        }
+        for chance in self.two_sided.iter() {
+            bytes.extend_from_slice(&quantize(*chance));
+        }
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, bytes)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<BearoffDatabase> {
+        let bytes = fs::read(path)?;
+        let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+        if bytes.len() < 20 || &bytes[..4] != BEAROFF_MAGIC {
+            return Err(invalid("not a bearoff database"));
+        }
+        let header = |index: usize| u32::from_le_bytes(bytes[4 + 4 * index..8 + 4 * index].try_into().unwrap()) as usize;
+        if header(0) != BEAROFF_VERSION as usize || header(1) != HOME_POINTS || header(2) != ONE_SIDED_NUTS || header(3) != TWO_SIDED_NUTS {
+            return Err(invalid("the bearoff database was generated for other sizes"));
+        }
+
+        let mut reader = Reader{
+            bytes: &bytes[20..]
+        };
+        let mut one_sided = Vec::with_capacity(position_count(ONE_SIDED_NUTS));
+        for _ in 0..position_count(ONE_SIDED_NUTS) {
+            let mut distributions = [Distribution::default(), Distribution::default()];
+            for distribution in distributions.iter_mut() {
+                let [start, length] = reader.take(2)?.try_into().unwrap();
+                distribution.start = start as usize;
+                distribution.probabilities = reader.chances(length as usize)?;
+            }
+            let [off, first_off] = distributions;
+            one_sided.push(OneSided{
+                off,
+                first_off
+            });
+        }
+        let two_sided = reader.chances(position_count(TWO_SIDED_NUTS).pow(2))?;
+        if !reader.bytes.is_empty() {
+            return Err(invalid("the bearoff database has trailing bytes"));
+        }
+        Ok(BearoffDatabase{
+            one_sided,
+            two_sided
+        })
+    }
+
+    // Chances for `player` right after they moved, none unless both players are bearing off without contact
+    pub fn probabilities(&self, board: &Board, player: usize) -> Option<Probabilities> {
+        let mover = home_position(board, player)?;
+        let on_roll = home_position(board, opponent(player))?;
+
+        let mover_nuts = nuts(&mover);
+        let on_roll_nuts = nuts(&on_roll);
+        if mover_nuts <= TWO_SIDED_NUTS && on_roll_nuts <= TWO_SIDED_NUTS {
+            // Without 15 nuts left nobody can be gammoned
+            let index = position_index(&on_roll, TWO_SIDED_NUTS) * position_count(TWO_SIDED_NUTS) + position_index(&mover, TWO_SIDED_NUTS);
+            return Some(Probabilities{
+                win: 1. - self.two_sided[index],
+                ..Probabilities::default()
+            });
+        }
+
+        let mover = &self.one_sided[position_index(&mover, ONE_SIDED_NUTS)];
+        let on_roll = &self.one_sided[position_index(&on_roll, ONE_SIDED_NUTS)];
+        // The player on roll finishing on their nth roll wins if the mover still needs n rolls or more
+        let on_roll_wins: f32 = on_roll.off.rolls().map(|(rolls, chance)| chance * mover.off.at_least(rolls)).sum();
+        let on_roll_gammons: f32 = if mover_nuts == ONE_SIDED_NUTS {
+            on_roll.off.rolls().map(|(rolls, chance)| chance * mover.first_off.at_least(rolls)).sum()
+        } else {
+            0.
+        };
+        let mover_gammons: f32 = if on_roll_nuts == ONE_SIDED_NUTS {
+            mover.off.rolls().map(|(rolls, chance)| chance * on_roll.first_off.at_least(rolls + 1)).sum()
+        } else {
+            0.
+        };
+        let win = (1. - on_roll_wins).clamp(0., 1.);
+        Some(Probabilities{
+            win,
+            win_gammon: mover_gammons.min(win),
+            lose_gammon: on_roll_gammons.min(1. - win),
+            ..Probabilities::default()
+        })
+    }
+}
+
+// Walks through the body of a database file
+struct Reader<'a> {
+    bytes: &'a [u8]
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < count {
+            return Err(io::Error::new(ErrorKind::InvalidData, "the bearoff database is truncated"));
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn chances(&mut self, count: usize) -> io::Result<Vec<f32>> {
+        Ok(self.take(2 * count)?.chunks(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]]) as f32 / PROBABILITY_SCALE).collect())
+    }
+}
+
+// `player`'s nuts on their home points, none when a nut is on the bar or outside the home board
+pub fn home_position(board: &Board, player: usize) -> Option<Position> {
+    if board.bar.nuts(player) > 0 {
+        return None;
+    }
+    let mut position = [0;HOME_POINTS];
+    for global_tower_index in 0..TOWERS_COUNT {
+        let nuts = board.nuts_of(player, global_tower_index);
+        if nuts == 0 {
+            continue;
+        }
+        let player_tower_index = to_player_tower_index(player, global_tower_index);
+        if player_tower_index < HOME_TOWER_INDEX {
+            return None;
+        }
+        position[TOWERS_COUNT - 1 - player_tower_index] = nuts;
+    }
+    Some(position)
+}
+
+fn nuts(position: &Position) -> usize {
+    position.iter().map(|nuts| *nuts as usize).sum()
+}
+
+fn pips(position: &Position) -> usize {
+    position.iter().enumerate().map(|(point, nuts)| (point + 1) * *nuts as usize).sum()
+}
+
+fn binomial(n: usize, k: usize) -> usize {
+    (0..k).fold(1, |result, i| result * (n - i) / (i + 1))
+}
+
+// Ways to spread at most `nuts` nuts over the home points
+pub fn position_count(nuts: usize) -> usize {
+    binomial(nuts + HOME_POINTS, HOME_POINTS)
+}
+
+// Rank of the position among those with at most `max_nuts` nuts, ordered by the nuts on the ace point, then the two point...
+pub fn position_index(position: &Position, max_nuts: usize) -> usize {
+    let mut index = 0;
+    let mut remaining = max_nuts;
+    for (point, nuts) in position.iter().enumerate() {
+        let later_points = HOME_POINTS - point - 1;
+        for fewer in 0..*nuts as usize {
+            index += binomial(remaining - fewer + later_points, later_points);
+        }
+        remaining -= *nuts as usize;
+    }
+    index
+}
+
+// Every position with at most `max_nuts` nuts, in index order
+fn positions(max_nuts: usize) -> Vec<Position> {
+    fn fill(position: &mut Position, point: usize, remaining: usize, positions: &mut Vec<Position>) {
+        if point == HOME_POINTS {
+            positions.push(*position);
+            return;
+        }
+        for nuts in 0..=remaining {
+            position[point] = nuts as u8;
+            fill(position, point + 1, remaining - nuts, positions);
+        }
+        position[point] = 0;
+    }
+    let mut positions = vec![];
+    fill(&mut [0;HOME_POINTS], 0, max_nuts, &mut positions);
+    positions
+}
+
+// Distinct positions the dice can leave, played by the board's own rules
+fn successors(position: &Position, dice: &[u8]) -> Vec<Position> {
+    let mut board = Board::empty();
+    for (point, nuts) in position.iter().enumerate() {
+        let tower = &mut board.towers[player_to_global_tower_index(PLAYER_HOST, TOWERS_COUNT - 1 - point)];
+        tower.nuts = *nuts;
+        tower.owner = PLAYER_HOST as u8;
+    }
+    *board.borne_off.nuts_mut(PLAYER_HOST) = NUTS_PER_PLAYER - nuts(position) as u8;
+    *board.borne_off.nuts_mut(PLAYER_GUEST) = NUTS_PER_PLAYER;
+    board.rehash();
+
+    board.unique_turns(PLAYER_HOST, dice).iter().map(|turn| {
+        let mut after = board.clone();
+        after.apply_turn(PLAYER_HOST, turn);
+        home_position(&after, PLAYER_HOST).unwrap()
+    }).collect()
+}
+
+// Positions of `positions` grouped by pip count, so a position comes after everything it can move to
+fn by_pips(positions: &[Position]) -> Vec<Vec<usize>> {
+    let max_pips = positions.iter().map(pips).max().unwrap_or(0);
+    let mut groups = vec![vec![]; max_pips + 1];
+    for (index, position) in positions.iter().enumerate() {
+        groups[pips(position)].push(index);
+    }
+    groups
+}
+
+fn mean(rolls: &[f64;MAX_ROLLS]) -> f64 {
+    rolls.iter().enumerate().map(|(count, chance)| count as f64 * chance).sum()
+}
+
+fn generate_one_sided() -> Vec<OneSided> {
+    let positions = positions(ONE_SIDED_NUTS);
+    let mut off = vec![[0.;MAX_ROLLS]; positions.len()];
+    let mut first_off = vec![[0.;MAX_ROLLS]; positions.len()];
+    let mut off_means = vec![0f64; positions.len()];
+    let mut first_off_means = vec![0f64; positions.len()];
+
+    for index in by_pips(&positions).into_iter().flatten() {
+        let position = &positions[index];
+        if nuts(position) == 0 {
+            off[index][0] = 1.;
+            first_off[index][0] = 1.;
+            continue;
+        }
+        for roll in ROLLS {
+            let chance = roll_probability(roll) as f64;
+            let successors: Vec<usize> = successors(position, &roll_to_dice(roll)).iter()
+                .map(|successor| position_index(successor, ONE_SIDED_NUTS))
+                .collect();
+
+            let best = *successors.iter().min_by(|first, second| off_means[**first].total_cmp(&off_means[**second])).unwrap();
+            for rolls in 0..MAX_ROLLS - 1 {
+                off[index][rolls + 1] += chance * off[best][rolls];
+            }
+
+            let bears_off = successors.iter().any(|successor| nuts(&positions[*successor]) < nuts(position));
+            if bears_off {
+                first_off[index][1] += chance;
+            } else {
+                let best = *successors.iter().min_by(|first, second| first_off_means[**first].total_cmp(&first_off_means[**second])).unwrap();
+                for rolls in 0..MAX_ROLLS - 1 {
+                    first_off[index][rolls + 1] += chance * first_off[best][rolls];
+                }
+            }
+        }
+        off_means[index] = mean(&off[index]);
+        first_off_means[index] = mean(&first_off[index]);
+    }
+
+    off.iter().zip(first_off.iter()).map(|(off, first_off)| OneSided{
+        off: Distribution::from_rolls(off),
+        first_off: Distribution::from_rolls(first_off)
+    }).collect()
+}
+
+fn generate_two_sided() -> Vec<f32> {
+    let positions = positions(TWO_SIDED_NUTS);
+    let count = positions.len();
+    let successors: Vec<Vec<Vec<usize>>> = positions.iter().map(|position| {
+        ROLLS.iter().map(|roll| {
+            successors(position, &roll_to_dice(*roll)).iter().map(|successor| position_index(successor, TWO_SIDED_NUTS)).collect()
+        }).collect()
+    }).collect();
+    let groups = by_pips(&positions);
+    // The empty position is index 0, whoever has it already won
+    let mut wins = vec![0f64; count * count];
+    for win in wins.iter_mut().take(count) {
+        *win = 1.;
+    }
+
+    // Every move lowers the pips, so a pair only looks up pairs with fewer pips in total
+    for total_pips in 1..2 * groups.len() - 1 {
+        for on_roll_pips in total_pips.saturating_sub(groups.len() - 1)..=total_pips.min(groups.len() - 1) {
+            for on_roll in groups[on_roll_pips].iter() {
+                for other in groups[total_pips - on_roll_pips].iter() {
+                    if *on_roll == 0 || *other == 0 {
+                        continue;
+                    }
+                    wins[on_roll * count + other] = ROLLS.iter().enumerate().map(|(roll_index, roll)| {
+                        let best = successors[*on_roll][roll_index].iter()
+                            .map(|successor| 1. - wins[other * count + successor])
+                            .fold(0., f64::max);
+                        roll_probability(*roll) as f64 * best
+                    }).sum();
+                }
+            }
+        }
+    }
+    wins.into_iter().map(|chance| chance as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shipped() -> BearoffDatabase {
+        BearoffDatabase::load(Path::new(env!("CARGO_MANIFEST_DIR")).join(BEAROFF_PATH)).unwrap()
+    }
+
+    fn one_point(point: usize, nuts: u8) -> Position {
+        let mut position = [0;HOME_POINTS];
+        position[point - 1] = nuts;
+        position
+    }
+
+    #[test]
+    fn positions_are_listed_in_index_order() {
+        for max_nuts in [TWO_SIDED_NUTS, ONE_SIDED_NUTS] {
+            let positions = positions(max_nuts);
+            assert_eq!(positions.len(), position_count(max_nuts));
+            for (index, position) in positions.iter().enumerate() {
+                assert_eq!(position_index(position, max_nuts), index, "{:?}", position);
+            }
+        }
+    }
+
+    #[test]
+    fn shipped_database_has_the_known_bearoffs() {
+        let database = shipped();
+        let off = |position: &Position| &database.one_sided[position_index(position, ONE_SIDED_NUTS)].off;
+
+        assert_eq!(off(&[0;HOME_POINTS]).start, 0);
+        // Any roll bears off two nuts on the ace point
+        let aces = off(&one_point(1, 2));
+        assert_eq!((aces.start, aces.probabilities.clone()), (1, vec![1.]));
+        // A nut on the six point misses with 11, 21, 31, 41 and 32, then comes off with any roll
+        let six = off(&one_point(6, 1));
+        assert_eq!(six.start, 1);
+        assert_eq!(six.probabilities.len(), 2);
+        assert!((six.probabilities[0] - 27. / 36.).abs() < 1e-4, "{:?}", six.probabilities);
+
+        let count = position_count(TWO_SIDED_NUTS);
+        let on_roll_wins = |on_roll: &Position, other: &Position| database.two_sided[position_index(on_roll, TWO_SIDED_NUTS) * count + position_index(other, TWO_SIDED_NUTS)];
+        assert_eq!(on_roll_wins(&one_point(1, 1), &one_point(1, 1)), 1.);
+        assert!((on_roll_wins(&one_point(6, 1), &one_point(1, 1)) - 27. / 36.).abs() < 1e-4);
+    }
+}
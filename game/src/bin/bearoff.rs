@@ -0,0 +1,24 @@
+// Generates the bearoff database the evaluators switch to in no-contact endgames.
+//
+//   bearoff [FILE]
+//
+// FILE defaults to assets/bearoff/bearoff.bin, where the game looks for it
+use std::env;
+use std::process;
+use std::time::Instant;
+
+use backgammon::bearoff::{BearoffDatabase, BEAROFF_PATH};
+
+fn main() {
+    let path = env::args().nth(1).unwrap_or(BEAROFF_PATH.to_string());
+
+    let started = Instant::now();
+    let database = BearoffDatabase::generate();
+    println!("Generated the bearoff database in {:.1?}", started.elapsed());
+
+    if let Err(error) = database.save(&path) {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    }
+    println!("Saved it to {}", path);
+}
@@ -0,0 +1,28 @@
+// The built-in bot served over the engine protocol on standard input and output, see `backgammon::engine`.
+//
+//   engine [LEVEL]
+//
+// LEVEL is a difficulty level, `beginner`, `intermediate`, `advanced` or `expert` (the default)
+use std::env;
+use std::io;
+use std::process;
+
+use backgammon::bot::{best_available_evaluator, Bot, Difficulty};
+use backgammon::engine::serve;
+use backgammon::utils::PLAYER_HOST;
+
+fn main() {
+    let difficulty = match env::args().nth(1) {
+        Some(level) => Difficulty::from_name(&level).unwrap_or_else(|| {
+            eprintln!("unknown level {}", level);
+            process::exit(2);
+        }),
+        None => Difficulty::Expert
+    };
+    let bot = Bot::with_difficulty(PLAYER_HOST, best_available_evaluator(), difficulty);
+    let name = format!("backgammon {}", difficulty.name());
+    if let Err(error) = serve(&name, &bot, io::stdin().lock(), io::stdout().lock()) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,61 @@
+// Rolls out a position given as a GNU Backgammon id, to settle close decisions.
+//
+//   rollout <PositionID:MatchID> [--trials N] [--seed S] [--threads T] [--depth D] [--weights FILE]
+//
+// With dice in the id the best candidate moves are rolled out with the same dice,
+// otherwise the position is rolled out for the player on roll
+use std::env;
+use std::process;
+use std::time::Instant;
+
+use backgammon::bot::{best_available_evaluator, Evaluator};
+use backgammon::gnubg::game_from_id;
+use backgammon::nn::Network;
+use backgammon::rollout::{rollout_on_roll, rollout_turns, RolloutConfig, RolloutResult};
+use backgammon::search::SearchConfig;
+use backgammon::utils::turn_notation;
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(2);
+}
+
+fn parse<T: std::str::FromStr>(flag: &str, value: &str) -> T {
+    value.parse().unwrap_or_else(|_| fail(format!("invalid value {} for {}", value, flag)))
+}
+
+fn print_result(label: &str, result: &RolloutResult) {
+    let probabilities = result.probabilities;
+    println!("{:<24} {:+.3} ± {:.3}  win {:.1}% gammon {:.1}% lost gammon {:.1}%", label, result.equity, result.standard_error,
+        100. * probabilities.win, 100. * probabilities.win_gammon, 100. * probabilities.lose_gammon);
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let id = args.next().unwrap_or_else(|| fail("usage: rollout <PositionID:MatchID> [--trials N] [--seed S] [--threads T] [--depth D] [--weights FILE]".to_string()));
+    let mut config = RolloutConfig::default();
+    let mut evaluator: Box<dyn Evaluator> = best_available_evaluator();
+    while let Some(flag) = args.next() {
+        let value = args.next().unwrap_or_else(|| fail(format!("{} needs a value", flag)));
+        match flag.as_str() {
+            "--trials" => config.trials = parse(&flag, &value),
+            "--seed" => config.seed = parse(&flag, &value),
+            "--threads" => config.threads = parse(&flag, &value),
+            "--depth" => config.search = SearchConfig::ply(parse(&flag, &value)),
+            "--weights" => evaluator = Box::new(Network::load(&value).unwrap_or_else(|error| fail(format!("{}: {}", value, error)))),
+            _ => fail(format!("unknown option {}", flag))
+        }
+    }
+
+    let (model, _) = game_from_id(&id).unwrap_or_else(|error| fail(format!("invalid id: {}", error)));
+    let player = model.active_player;
+    let started = Instant::now();
+    if model.has_rolled() {
+        for (turn, result) in rollout_turns(&model.board, player, &model.dice, evaluator.as_ref(), config) {
+            print_result(&turn_notation(player, &turn), &result);
+        }
+    } else {
+        print_result("on roll", &rollout_on_roll(&model.board, player, evaluator.as_ref(), config));
+    }
+    println!("{} trials per rollout in {:.1?}", config.trials, started.elapsed());
+}
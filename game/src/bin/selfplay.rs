@@ -0,0 +1,195 @@
+// Engine against engine games without a window, to check rule changes and compare bot strength.
+//
+//   selfplay [--games N] [--seed S] [--a SPEC] [--b SPEC]
+//
+// A SPEC is `heuristic`, `nn` or the path of a weights file, optionally followed by `:<depth>`,
+// a difficulty level (`beginner`, `intermediate`, `advanced`, `expert`) played with the best evaluator,
+// or `engine:<command line>` for an engine speaking the engine protocol, e.g. `engine:./engine beginner`.
+// Games are cubeless, A and B swap seats every game and game `i` is played with the seed `S + i`
+use std::env;
+use std::io;
+use std::process;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use backgammon::bot::{best_available_evaluator, Bot, Difficulty, Evaluator, HeuristicEvaluator};
+use backgammon::engine::EngineClient;
+use backgammon::model::{random_roll, GameModel};
+use backgammon::nn::{Network, WEIGHTS_PATH};
+use backgammon::search::SearchConfig;
+use backgammon::utils::{opponent, ResultType, Turn, PLAYER_GUEST, PLAYER_HOST};
+
+const DEFAULT_GAMES: u64 = 1000;
+// Two sided 95% confidence
+const CONFIDENCE_Z: f64 = 1.96;
+
+struct Options {
+    games: u64,
+    seed: u64,
+    specs: [String;2]
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options{
+        games: DEFAULT_GAMES,
+        seed: 0,
+        specs: ["heuristic:1".to_string(), "heuristic:0".to_string()]
+    };
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--games" => options.games = value.parse().map_err(|_| format!("invalid game count {}", value))?,
+            "--seed" => options.seed = value.parse().map_err(|_| format!("invalid seed {}", value))?,
+            "--a" => options.specs[0] = value,
+            "--b" => options.specs[1] = value,
+            _ => return Err(format!("unknown option {}", flag))
+        }
+    }
+    Ok(options)
+}
+
+// One side of the games, a built-in bot or an external engine
+enum Contestant {
+    Bot(Bot),
+    Engine(EngineClient)
+}
+
+impl Contestant {
+    fn seat(&mut self, player: usize, noise_seed: u64) {
+        if let Contestant::Bot(bot) = self {
+            bot.player = player;
+            // Noisy bots make reproducible mistakes too
+            bot.noise_seed = noise_seed;
+        }
+    }
+
+    fn new_game(&mut self) -> io::Result<()> {
+        match self {
+            Contestant::Bot(_) => Ok(()),
+            Contestant::Engine(engine) => engine.new_game()
+        }
+    }
+
+    fn choose_turn(&mut self, model: &GameModel) -> io::Result<Turn> {
+        match self {
+            Contestant::Bot(bot) => Ok(bot.choose_turn(model)),
+            Contestant::Engine(engine) => engine.choose_turn(&model.board, model.active_player, model.roll.unwrap())
+        }
+    }
+}
+
+fn build_contestant(spec: &str, player: usize) -> Result<Contestant, String> {
+    if let Some(command) = spec.strip_prefix("engine:") {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or(format!("no engine command in {}", spec))?;
+        let args: Vec<&str> = words.collect();
+        return EngineClient::spawn(program, &args).map(Contestant::Engine).map_err(|error| format!("{}: {}", program, error));
+    }
+    if let Some(difficulty) = Difficulty::from_name(spec) {
+        return Ok(Contestant::Bot(Bot::with_difficulty(player, best_available_evaluator(), difficulty)));
+    }
+    let (name, depth) = match spec.rsplit_once(':') {
+        Some((name, depth)) => (name, depth.parse().map_err(|_| format!("invalid depth in {}", spec))?),
+        None => (spec, 0)
+    };
+    let evaluator: Box<dyn Evaluator> = match name {
+        "heuristic" => Box::new(HeuristicEvaluator::default()),
+        "nn" => Box::new(Network::load(WEIGHTS_PATH).map_err(|error| format!("{}: {}", WEIGHTS_PATH, error))?),
+        path => Box::new(Network::load(path).map_err(|error| format!("{}: {}", path, error))?)
+    };
+    Ok(Contestant::Bot(Bot::new(player, evaluator, SearchConfig::ply(depth))))
+}
+
+// Outcomes counted for each bot, A first
+#[derive(Default)]
+struct Stats {
+    wins: [u64;2],
+    gammons: [u64;2],
+    backgammons: [u64;2],
+    turns: u64,
+    // A's points, minus B's, of every game
+    points: Vec<f64>
+}
+
+impl Stats {
+    fn mean_points(&self) -> f64 {
+        self.points.iter().sum::<f64>() / self.points.len() as f64
+    }
+
+    fn confidence(&self) -> f64 {
+        let count = self.points.len() as f64;
+        if count < 2. {
+            return f64::INFINITY;
+        }
+        let mean = self.mean_points();
+        let variance = self.points.iter().map(|points| (points - mean).powi(2)).sum::<f64>() / (count - 1.);
+        CONFIDENCE_Z * (variance / count).sqrt()
+    }
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    100. * count as f64 / total as f64
+}
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(2);
+    });
+    let mut contestants = [PLAYER_HOST, PLAYER_GUEST].map(|player| {
+        build_contestant(&options.specs[player], player).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(2);
+        })
+    });
+    let fail = |error: io::Error| -> ! {
+        eprintln!("{}", error);
+        process::exit(1);
+    };
+
+    let started = Instant::now();
+    let mut stats = Stats::default();
+    for game in 0..options.games {
+        let mut rng = StdRng::seed_from_u64(options.seed.wrapping_add(game));
+        // Bot A plays the host in even games
+        let seat_of_a = if game % 2 == 0 { PLAYER_HOST } else { PLAYER_GUEST };
+        for (index, contestant) in contestants.iter_mut().enumerate() {
+            let seat = if index == 0 { seat_of_a } else { opponent(seat_of_a) };
+            contestant.seat(seat, options.seed.wrapping_add(game) ^ index as u64);
+            contestant.new_game().unwrap_or_else(|error| fail(error));
+        }
+
+        let mut model = GameModel::new();
+        while !model.is_over() {
+            model.roll_dice(random_roll(&mut rng)).unwrap();
+            let contestant = if model.active_player == seat_of_a { &mut contestants[0] } else { &mut contestants[1] };
+            let turn = contestant.choose_turn(&model).unwrap_or_else(|error| fail(error));
+            model.play_turn(&turn).unwrap();
+            stats.turns += 1;
+        }
+
+        let result = model.result.unwrap();
+        let winner = if result.winner == seat_of_a { 0 } else { 1 };
+        stats.wins[winner] += 1;
+        if result.result_type != ResultType::Single {
+            stats.gammons[winner] += 1;
+        }
+        if result.result_type == ResultType::Backgammon {
+            stats.backgammons[winner] += 1;
+        }
+        let points = result.points() as f64;
+        stats.points.push(if winner == 0 { points } else { -points });
+    }
+
+    let games = options.games.max(1);
+    println!("A {} vs B {}, {} games from seed {} in {:.1?}", options.specs[0], options.specs[1], options.games, options.seed, started.elapsed());
+    for (index, name) in ["A", "B"].into_iter().enumerate() {
+        println!("{} wins {:.1}%, gammons {:.1}%, backgammons {:.1}%", name,
+            percent(stats.wins[index], games), percent(stats.gammons[index], games), percent(stats.backgammons[index], games));
+    }
+    println!("Average game length {:.1} turns", stats.turns as f64 / games as f64);
+    println!("A points per game {:+.3} ± {:.3} (95% confidence)", stats.mean_points(), stats.confidence());
+}
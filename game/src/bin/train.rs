@@ -0,0 +1,81 @@
+// Trains the neural network evaluator by self-play, without a window.
+//
+//   train [--games N] [--weights FILE] [--hidden H] [--seed S]
+//
+// Training carries on from the weights in FILE when it holds any, and saves them back every 1000 games.
+// The game and the other tools pick up assets/nn/weights.bin, the default FILE, once it exists
+use std::env;
+use std::process;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use backgammon::nn::{Network, TrainingConfig, DEFAULT_HIDDEN, WEIGHTS_PATH};
+use backgammon::utils::{ResultType, PLAYER_HOST};
+
+const DEFAULT_GAMES: usize = 10_000;
+// Games between progress reports and weight saves
+const REPORT_GAMES: usize = 1000;
+
+struct Options {
+    games: usize,
+    weights: String,
+    hidden: usize,
+    seed: Option<u64>
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options{
+        games: DEFAULT_GAMES,
+        weights: WEIGHTS_PATH.to_string(),
+        hidden: DEFAULT_HIDDEN,
+        seed: None
+    };
+    let mut args = env::args().skip(1);
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or(format!("{} needs a value", flag))?;
+        match flag.as_str() {
+            "--games" => options.games = value.parse().map_err(|_| format!("invalid game count {}", value))?,
+            "--weights" => options.weights = value,
+            "--hidden" => options.hidden = value.parse().map_err(|_| format!("invalid hidden unit count {}", value))?,
+            "--seed" => options.seed = Some(value.parse().map_err(|_| format!("invalid seed {}", value))?),
+            _ => return Err(format!("unknown option {}", flag))
+        }
+    }
+    Ok(options)
+}
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(2);
+    });
+    let mut rng = options.seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+    let mut network = match Network::load(&options.weights) {
+        Ok(network) => {
+            println!("Training {} from its saved weights", options.weights);
+            network
+        },
+        Err(_) => Network::new(options.hidden, &mut rng)
+    };
+
+    let started = Instant::now();
+    let mut host_wins = 0;
+    let mut gammons = 0;
+    for game in 1..=options.games {
+        let result = network.train_game(TrainingConfig::default(), &mut rng);
+        if result.winner == PLAYER_HOST {
+            host_wins += 1;
+        }
+        if result.result_type != ResultType::Single {
+            gammons += 1;
+        }
+        if game % REPORT_GAMES == 0 || game == options.games {
+            println!("{} games in {:.0?}, host won {}, gammons {}", game, started.elapsed(), host_wins, gammons);
+            if let Err(error) = network.save(&options.weights) {
+                eprintln!("Can't save {}: {}", options.weights, error);
+            }
+        }
+    }
+}
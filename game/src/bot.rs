@@ -0,0 +1,391 @@
+use std::sync::Arc;
+
+use crate::bearoff::bearoff_equity;
+use crate::model::GameModel;
+use crate::nn::{Network, WEIGHTS_PATH};
+use crate::search::{Search, SearchConfig};
+use crate::utils::{opponent, to_player_tower_index, Board, Turn, HOME_TOWER_INDEX, TOWERS_COUNT};
+
+// Pips a nut on the bar still has to travel
+const BAR_PIPS: u32 = TOWERS_COUNT as u32 + 1;
+// Index of the bar point, the tower right before the home board
+const BAR_POINT_INDEX: usize = HOME_TOWER_INDEX - 1;
+// The opponent's home board, where made points are anchors
+const ANCHOR_TOWERS: usize = 6;
+// Half an average roll, what being on roll is worth in a race
+const ON_ROLL_PIPS: f32 = 4.;
+// Raw heuristic scores are squashed into an equity, a score of this size is worth about 0.76
+const SCORE_SCALE: f32 = 10.;
+// Equity the bot needs on roll to turn the cube, and the least it accepts when doubled
+const DOUBLE_THRESHOLD: f32 = 0.45;
+const TAKE_THRESHOLD: f32 = -0.5;
+// Evaluation errors of the weaker difficulty levels, compare them with e.g. `selfplay --a advanced --b expert`
+const BEGINNER_NOISE: f32 = 0.06;
+const INTERMEDIATE_NOISE: f32 = 0.025;
+const ADVANCED_NOISE: f32 = 0.01;
+
+// Scores a position right after `player` moved, with the opponent on roll.
+// The result is the equity for `player`, from -1 (sure loss) to 1 (sure win) in a single game,
+// decided games score their gammon or backgammon points
+pub trait Evaluator: Send + Sync {
+    fn evaluate(&self, board: &Board, player: usize) -> f32;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct HeuristicWeights {
+    // Per percent of lead in the race
+    pub pip: f32,
+    pub blot: f32,
+    // Per pip lost by a blot, times the chance it gets hit next roll
+    pub exposure: f32,
+    pub point: f32,
+    pub home_point: f32,
+    pub bar_point: f32,
+    // Per tower of the longest run of consecutive points, squared
+    pub prime: f32,
+    pub anchor: f32,
+    pub opponent_on_bar: f32,
+    pub borne_off: f32
+}
+
+impl Default for HeuristicWeights {
+    fn default() -> HeuristicWeights {
+        HeuristicWeights{
+            pip: 0.6,
+            blot: 0.5,
+            exposure: 0.15,
+            point: 0.5,
+            home_point: 0.8,
+            bar_point: 0.8,
+            prime: 0.15,
+            anchor: 1.,
+            opponent_on_bar: 1.,
+            borne_off: 0.3
+        }
+    }
+}
+
+// Hand tuned evaluation of the usual backgammon ideas: race, safety, points, primes and anchors
+#[derive(Clone, Debug, Default)]
+pub struct HeuristicEvaluator {
+    pub weights: HeuristicWeights
+}
+
+impl Evaluator for HeuristicEvaluator {
+    fn evaluate(&self, board: &Board, player: usize) -> f32 {
+        if let Some(result) = board.result() {
+            let points = result.points() as f32;
+            return if result.winner == player { points } else { -points };
+        }
+        // Races where both sides are bearing off are looked up instead
+        if let Some(equity) = bearoff_equity(board, player) {
+            return equity;
+        }
+
+        let weights = &self.weights;
+        let other = opponent(player);
+        let mine = Layout::new(board, player);
+        let theirs = Layout::new(board, other);
+
+        let their_pips = theirs.pips as f32 - ON_ROLL_PIPS;
+        let mut score = weights.pip * 100. * (their_pips - mine.pips as f32) / their_pips.max(mine.pips as f32).max(1.);
+        score += weights.borne_off * (board.borne_off.nuts(player) as f32 - board.borne_off.nuts(other) as f32);
+        score += weights.opponent_on_bar * (board.bar.nuts(other) as f32 - board.bar.nuts(player) as f32);
+
+        // Once the nuts have passed each other only the race is left
+        if mine.is_past(&theirs) {
+            return (score / SCORE_SCALE).tanh();
+        }
+
+        for (layout, sign) in [(&mine, 1.), (&theirs, -1.)] {
+            let mut structure = 0.;
+            for tower_index in 0..TOWERS_COUNT {
+                if layout.nuts[tower_index] < 2 {
+                    continue;
+                }
+                structure += if tower_index >= HOME_TOWER_INDEX {
+                    weights.home_point
+                } else if tower_index == BAR_POINT_INDEX {
+                    weights.bar_point
+                } else if tower_index < ANCHOR_TOWERS {
+                    weights.anchor
+                } else {
+                    weights.point
+                };
+            }
+            let prime = layout.longest_prime() as f32;
+            structure += weights.prime * prime * prime;
+            score += sign * structure;
+        }
+
+        // The opponent rolls next, so only our blots can be hit right away
+        for tower_index in 0..TOWERS_COUNT {
+            if mine.nuts[tower_index] == 1 {
+                let pips_lost = (tower_index + 1) as f32;
+                score -= weights.blot + weights.exposure * pips_lost * mine.hit_chance(&theirs, tower_index);
+            }
+            if theirs.nuts[tower_index] == 1 {
+                score += weights.blot;
+            }
+        }
+
+        (score / SCORE_SCALE).tanh()
+    }
+}
+
+// A player's nuts per tower, indexed in their own direction of travel
+struct Layout {
+    nuts: [u8;TOWERS_COUNT],
+    on_bar: u8,
+    pips: u32
+}
+
+impl Layout {
+    fn new(board: &Board, player: usize) -> Layout {
+        let mut nuts = [0;TOWERS_COUNT];
+        for global_tower_index in 0..TOWERS_COUNT {
+            nuts[to_player_tower_index(player, global_tower_index)] = board.nuts_of(player, global_tower_index);
+        }
+        let on_bar = board.bar.nuts(player);
+        let pips = nuts.iter().enumerate().map(|(tower_index, count)| (TOWERS_COUNT - tower_index) as u32 * *count as u32).sum::<u32>()
+            + BAR_PIPS * on_bar as u32;
+        Layout{
+            nuts,
+            on_bar,
+            pips
+        }
+    }
+
+    // Our towers mirrored into the opponent's indices
+    fn mirrored(tower_index: usize) -> usize {
+        TOWERS_COUNT - 1 - tower_index
+    }
+
+    fn is_past(&self, theirs: &Layout) -> bool {
+        if self.on_bar > 0 || theirs.on_bar > 0 {
+            return false;
+        }
+        let Some(my_rearmost) = self.nuts.iter().position(|count| *count > 0) else {
+            return true;
+        };
+        let Some(their_rearmost) = theirs.nuts.iter().position(|count| *count > 0) else {
+            return true;
+        };
+        // Their rearmost nut, seen from our side, is already behind ours
+        Layout::mirrored(their_rearmost) < my_rearmost
+    }
+
+    fn longest_prime(&self) -> usize {
+        let mut longest = 0;
+        let mut current = 0;
+        for count in self.nuts.iter() {
+            current = if *count >= 2 { current + 1 } else { 0 };
+            longest = longest.max(current);
+        }
+        longest
+    }
+
+    // Chance the opponent hits our blot at `tower_index` with their next roll, ignoring blocked paths
+    fn hit_chance(&self, theirs: &Layout, tower_index: usize) -> f32 {
+        // Distances from each opponent nut to the blot, the bar enters one tower past their start
+        let mut distances: Vec<usize> = vec![];
+        for (their_index, count) in theirs.nuts.iter().enumerate() {
+            let target = Layout::mirrored(tower_index);
+            if *count > 0 && their_index < target {
+                distances.push(target - their_index);
+            }
+        }
+        if theirs.on_bar > 0 {
+            distances.push(Layout::mirrored(tower_index) + 1);
+        }
+
+        let mut hitting_rolls = 0;
+        for first in 1..=6 {
+            for second in 1..=6 {
+                let reach: Vec<usize> = if first == second {
+                    (1..=4).map(|times| first * times).collect()
+                } else {
+                    vec![first, second, first + second]
+                };
+                if distances.iter().any(|distance| reach.contains(distance)) {
+                    hitting_rolls += 1;
+                }
+            }
+        }
+        hitting_rolls as f32 / 36.
+    }
+}
+
+// The trained network when its weights are around, the heuristic otherwise
+pub fn best_available_evaluator() -> Box<dyn Evaluator> {
+    match Network::load(WEIGHTS_PATH) {
+        Ok(network) => Box::new(network),
+        Err(_) => Box::new(HeuristicEvaluator::default())
+    }
+}
+
+// Playing strength of the computer opponent. Weaker levels search less and misjudge every position by
+// a random error, so they still play sensible moves most of the time and only miss the close calls
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Difficulty {
+    Beginner,
+    #[default]
+    Intermediate,
+    Advanced,
+    Expert
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty;4] = [Difficulty::Beginner, Difficulty::Intermediate, Difficulty::Advanced, Difficulty::Expert];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Beginner => "Beginner",
+            Difficulty::Intermediate => "Intermediate",
+            Difficulty::Advanced => "Advanced",
+            Difficulty::Expert => "Expert"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        Difficulty::ALL.into_iter().find(|difficulty| difficulty.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn search(&self) -> SearchConfig {
+        match self {
+            Difficulty::Beginner | Difficulty::Intermediate => SearchConfig::ply(0),
+            Difficulty::Advanced => SearchConfig::ply(1),
+            // Only the expert looks past the opponent's replies
+            Difficulty::Expert => SearchConfig::ply(2)
+        }
+    }
+
+    // Standard deviation of the error added to every evaluation, in points
+    pub fn noise(&self) -> f32 {
+        match self {
+            Difficulty::Beginner => BEGINNER_NOISE,
+            Difficulty::Intermediate => INTERMEDIATE_NOISE,
+            Difficulty::Advanced => ADVANCED_NOISE,
+            Difficulty::Expert => 0.
+        }
+    }
+}
+
+// Adds a gaussian error to the evaluation of every undecided position. The error only depends on the
+// seed and the position, so a position is misjudged the same way everywhere the search meets it
+struct NoisyEvaluator<'a> {
+    evaluator: &'a dyn Evaluator,
+    deviation: f32,
+    seed: u64
+}
+
+impl Evaluator for NoisyEvaluator<'_> {
+    fn evaluate(&self, board: &Board, player: usize) -> f32 {
+        let equity = self.evaluator.evaluate(board, player);
+        if self.deviation == 0. || board.result().is_some() {
+            return equity;
+        }
+        let bits = mix(self.seed ^ mix(board.zobrist().wrapping_add(player as u64)));
+        // Box-Muller from the two halves of the hash
+        let uniform = |half: u64| ((half & 0xffff_ffff) as f32 + 1.) / (u32::MAX as f32 + 2.);
+        let (first, second) = (uniform(bits), uniform(bits >> 32));
+        let gaussian = (-2. * first.ln()).sqrt() * (std::f32::consts::TAU * second).cos();
+        equity + self.deviation * gaussian
+    }
+}
+
+// splitmix64 finalizer
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+// Plays one side of a game: moves, cube offers and cube answers, searching ahead with its evaluator
+pub struct Bot {
+    pub player: usize,
+    pub search: SearchConfig,
+    // Standard deviation of the error the bot makes judging positions, see `Difficulty::noise`
+    pub noise: f32,
+    // Picks the errors, the same seed makes the same mistakes in the same positions
+    pub noise_seed: u64,
+    // Shared so an analysis can keep using it on another thread
+    evaluator: Arc<dyn Evaluator>
+}
+
+impl Bot {
+    pub fn new(player: usize, evaluator: Box<dyn Evaluator>, search: SearchConfig) -> Bot {
+        Bot{
+            player,
+            search,
+            noise: 0.,
+            noise_seed: 0,
+            evaluator: Arc::from(evaluator)
+        }
+    }
+
+    pub fn heuristic(player: usize) -> Bot {
+        Bot::new(player, Box::new(HeuristicEvaluator::default()), SearchConfig::default())
+    }
+
+    pub fn best_available(player: usize) -> Bot {
+        Bot::new(player, best_available_evaluator(), SearchConfig::default())
+    }
+
+    pub fn with_difficulty(player: usize, evaluator: Box<dyn Evaluator>, difficulty: Difficulty) -> Bot {
+        Bot{
+            noise: difficulty.noise(),
+            noise_seed: rand::random(),
+            ..Bot::new(player, evaluator, difficulty.search())
+        }
+    }
+
+    // The evaluator without the bot's errors, for hints and analysis
+    pub fn evaluator(&self) -> &dyn Evaluator {
+        self.evaluator.as_ref()
+    }
+
+    pub fn shared_evaluator(&self) -> Arc<dyn Evaluator> {
+        self.evaluator.clone()
+    }
+
+    // The evaluator the bot plays with, its errors change with the position and the dice
+    fn judgement(&self, model: &GameModel) -> NoisyEvaluator<'_> {
+        NoisyEvaluator{
+            evaluator: self.evaluator(),
+            deviation: self.noise,
+            seed: self.noise_seed ^ model.zobrist()
+        }
+    }
+
+    // The best turn for the rolled dice, an empty turn when no nut can move
+    pub fn choose_turn(&self, model: &GameModel) -> Turn {
+        Search::new(&self.judgement(model), self.search).best_turn(&model.board, model.active_player, &model.dice)
+    }
+
+    pub fn should_double(&self, model: &GameModel) -> bool {
+        model.cube.can_double(self.player)
+            && Search::new(&self.judgement(model), self.search).evaluate_on_roll(&model.board, self.player) >= DOUBLE_THRESHOLD
+    }
+
+    // The doubling player is on roll, so this is our equity right after our last move
+    pub fn should_take(&self, model: &GameModel) -> bool {
+        Search::new(&self.judgement(model), self.search).evaluate(&model.board, self.player, self.search.depth) >= TAKE_THRESHOLD
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_level_searches_deeper_or_errs_less_than_the_one_below() {
+        for pair in Difficulty::ALL.windows(2) {
+            let (lower, higher) = (pair[0], pair[1]);
+            assert!(higher.search().depth >= lower.search().depth, "{} searches less than {}", higher.name(), lower.name());
+            assert!(higher.noise() <= lower.noise(), "{} errs more than {}", higher.name(), lower.name());
+            assert_ne!((higher.search().depth, higher.noise()), (lower.search().depth, lower.noise()), "{} plays like {}", higher.name(), lower.name());
+        }
+    }
+}
@@ -0,0 +1,126 @@
+// How the player's actions in an on-chain game are passed to the contract's entry functions.
+// The contract moves nuts from global towers like `Board` does, but bears them off from towers
+// counted from the player's ace point, and picks dice by their index among the dice left
+use crate::model::GameModel;
+use crate::utils::{to_player_tower_index, Move, OFF_INDEX, TOWERS_COUNT};
+
+// What the player can do in an on-chain game besides creating, joining and cleaning it up
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChainAction {
+    Roll,
+    // Moves a nut from the global tower, or enters one from the bar with `BAR_INDEX`, by the die at
+    // `dice_index` of the dice left
+    Move{ tower_index: u8, dice_index: u8 },
+    // Bears a nut off from the tower numbered from the player's ace point, 0 to 5
+    BearOff{ player_tower_index: u8, dice_index: u8 },
+    // Gives up the dice left when none of them can be played
+    Pass,
+    Forfeit
+}
+
+impl ChainAction {
+    // The contract's call for a legal move of the active player
+    pub fn for_move(model: &GameModel, mv: &Move) -> Option<ChainAction> {
+        let dice_index = model.dice.iter().position(|die| *die == mv.die)? as u8;
+        Some(if mv.to == OFF_INDEX {
+            ChainAction::BearOff{
+                player_tower_index: ace_tower_index(model.active_player, mv.from) as u8,
+                dice_index
+            }
+        } else {
+            // The contract's bar has the same index as `BAR_INDEX`
+            ChainAction::Move{
+                tower_index: mv.from as u8,
+                dice_index
+            }
+        })
+    }
+}
+
+// The tower counted from `player`'s ace point, the contract's `global_to_player_tower_index`
+pub fn ace_tower_index(player: usize, global_tower_index: usize) -> usize {
+    TOWERS_COUNT - 1 - to_player_tower_index(player, global_tower_index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::ROLLS;
+    use crate::utils::{initialize, player_to_global_tower_index, roll_to_dice, Board, Tower, BAR_INDEX, PLAYER_GUEST, PLAYER_HOST};
+
+    // The contract's `BAR_TOWER_INDEX`
+    const CONTRACT_BAR: u8 = 24;
+
+    // The move `action` makes in the contract's `place_move` and `bear_off`, which read the dice from 0 to 5.
+    // Both number the towers with their own functions, copied here
+    fn contract_move(player: usize, action: ChainAction, dice: &[u8]) -> Move {
+        let player_to_global = |index: usize| if player == PLAYER_HOST { index } else { TOWERS_COUNT - index - 1 };
+        let global_to_player = |index: usize| if player == PLAYER_HOST { TOWERS_COUNT - index - 1 } else { index };
+        match action {
+            ChainAction::Move{ tower_index, dice_index } => {
+                let pips = dice[dice_index as usize];
+                let (from, to) = if tower_index == CONTRACT_BAR {
+                    (BAR_INDEX, pips as usize - 1)
+                } else {
+                    (tower_index as usize, player_to_global(tower_index as usize) + pips as usize)
+                };
+                Move{ from, to: player_to_global(to), die: pips, hit: false }
+            }
+            ChainAction::BearOff{ player_tower_index, dice_index } => Move{
+                from: global_to_player(player_tower_index as usize),
+                to: OFF_INDEX,
+                die: dice[dice_index as usize],
+                hit: false
+            },
+            _ => panic!("{:?} doesn't move a nut", action)
+        }
+    }
+
+    fn assert_encoded(board: &Board, player: usize) {
+        for roll in ROLLS {
+            let mut model = GameModel::new();
+            model.board = board.clone();
+            model.active_player = player;
+            model.roll = Some(roll);
+            model.dice = roll_to_dice(roll);
+            for mv in model.legal_moves() {
+                let action = ChainAction::for_move(&model, &mv).unwrap();
+                let played = contract_move(player, action, &model.dice);
+                assert_eq!((played.from, played.to, played.die), (mv.from, mv.to, mv.die), "{:?} sent as {:?}", mv, action);
+            }
+        }
+    }
+
+    #[test]
+    fn moves_reach_the_same_towers_in_the_contract() {
+        let mut entering = initialize();
+        for player in [PLAYER_HOST, PLAYER_GUEST] {
+            entering.towers[player_to_global_tower_index(player, 0)].nuts -= 1;
+            *entering.bar.nuts_mut(player) += 1;
+        }
+        entering.rehash();
+
+        let mut bearing_off = Board::empty();
+        for player in [PLAYER_HOST, PLAYER_GUEST] {
+            for point in [1, 3, 5] {
+                bearing_off.towers[player_to_global_tower_index(player, TOWERS_COUNT - point)] = Tower{ nuts: 1, owner: player as u8 };
+            }
+            *bearing_off.borne_off.nuts_mut(player) = 12;
+        }
+        bearing_off.rehash();
+
+        for board in [initialize(), entering, bearing_off] {
+            for player in [PLAYER_HOST, PLAYER_GUEST] {
+                assert_encoded(&board, player);
+            }
+        }
+    }
+
+    #[test]
+    fn bear_offs_count_from_the_ace_point() {
+        assert_eq!(ace_tower_index(PLAYER_HOST, TOWERS_COUNT - 1), 0);
+        assert_eq!(ace_tower_index(PLAYER_HOST, TOWERS_COUNT - 6), 5);
+        assert_eq!(ace_tower_index(PLAYER_GUEST, 0), 0);
+        assert_eq!(ace_tower_index(PLAYER_GUEST, 5), 5);
+    }
+}
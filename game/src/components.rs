@@ -18,3 +18,7 @@ impl  Id {
         return Id { id: value }
     }
 }
+
+// Sprites and texts showing the game model, redrawn whenever it changes
+#[derive(Component)]
+pub struct BoardPiece;
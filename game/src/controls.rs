@@ -0,0 +1,393 @@
+use std::sync::Arc;
+
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy::tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task};
+
+use anyhow::Result;
+use bevy_async_task::{AsyncTaskRunner, AsyncTaskStatus};
+
+use crate::analysis::{analyze, Analysis};
+use crate::bot::{best_available_evaluator, Evaluator};
+use crate::components::Id;
+use crate::drawing::get_tower_at;
+use crate::gnubg::game_id;
+use crate::model::{random_roll, GameModel};
+use crate::chain::ChainAction;
+use crate::gdk::OnChainGame;
+use crate::resources::{Backend, Game, Hint, Review};
+use crate::states::GameState;
+use crate::search::{Search, SearchConfig};
+use crate::utils::Move;
+
+const BOT_STEP_SECONDS: f32 = 0.8;
+// Number keys preview the hinted turns, pressing the same key again plays it
+const HINT_KEYS: [KeyCode;5] = [KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4, KeyCode::Digit5];
+
+// Keyboard and mouse input for the active player while a game is on the board
+pub fn game_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    // On-chain games are played in `chain_game_input`
+    if game.chain_player.is_some() {
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::MainMenu);
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::KeyI) {
+        info!("{}", game_id(game.model.as_ref().unwrap(), game.current_match.as_ref()));
+    }
+
+    // The computer plays its own turns in `bot_turn`
+    let active_player = game.model.as_ref().unwrap().active_player;
+    if game.bot.as_ref().map_or(false, |bot| bot.player == active_player) {
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::Space) {
+        let model = game.model.as_mut().unwrap();
+        if let Err(error) = model.roll_dice(random_roll(&mut rand::thread_rng())) {
+            println!("Can't roll: {}", error);
+        }
+    }
+
+    if keys.just_pressed(KeyCode::KeyD) {
+        let model = game.model.as_mut().unwrap();
+        match model.offer_double() {
+            Ok(()) => next_state.set(GameState::DoubleOffered),
+            Err(error) => println!("Can't double: {}", error)
+        }
+    }
+
+    if keys.just_pressed(KeyCode::KeyH) {
+        game.hint = if game.current_hint().is_some() { None } else { rank_hints(&game) };
+    }
+
+    for (index, key) in HINT_KEYS.iter().enumerate() {
+        if keys.just_pressed(*key) {
+            select_hint(&mut game, index);
+        }
+    }
+
+    if keys.just_pressed(KeyCode::Backspace) {
+        game.model.as_mut().unwrap().undo_move();
+    }
+
+    if keys.just_pressed(KeyCode::Enter) {
+        let model = game.model.as_mut().unwrap();
+        if let Err(error) = model.end_turn() {
+            println!("Can't end turn: {}", error);
+        }
+    }
+
+    if let Some(mv) = clicked_move(game.model.as_ref().unwrap(), &mouse, &windows, &cameras) {
+        if let Err(error) = game.model.as_mut().unwrap().play_move(mv) {
+            println!("Can't move: {}", error);
+        }
+    }
+}
+
+// Left click moves a nut from the clicked tower with the higher die, right click with the lower one
+fn clicked_move(
+    model: &GameModel,
+    mouse: &ButtonInput<MouseButton>,
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Move> {
+    let prefer_higher = mouse.just_pressed(MouseButton::Left);
+    if !prefer_higher && !mouse.just_pressed(MouseButton::Right) {
+        return None;
+    }
+    let from = get_tower_at(cursor_position(windows, cameras)?)?;
+
+    let mut candidates: Vec<Move> = model.legal_moves().into_iter().filter(|mv| mv.from == from).collect();
+    candidates.sort_by_key(|mv| mv.die);
+    if prefer_higher {
+        candidates.last().copied()
+    } else {
+        candidates.first().copied()
+    }
+}
+
+/*
+ * Input for an on-chain game. The contract holds the game, so rolls and moves are sent as transactions
+ * and the board only changes once it's read back. Space rolls, clicks move and Enter passes once no die
+ * can be played. Nothing can be done while the opponent is to play, and there is no undo, cube or hint
+ */
+pub fn chain_game_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    backend: Res<Backend>,
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut task_executor: AsyncTaskRunner<Result<OnChainGame>>,
+) {
+    let Some(chain_player) = game.chain_player else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::MainMenu);
+        return;
+    }
+
+    match task_executor.poll() {
+        AsyncTaskStatus::Idle => (),
+        // One transaction at a time, the next one needs the board this one leaves
+        AsyncTaskStatus::Pending => return,
+        AsyncTaskStatus::Finished(Ok(chain_game)) => {
+            game.model = Some(chain_game.to_model());
+            return;
+        }
+        AsyncTaskStatus::Finished(Err(error)) => {
+            println!("{:#}", error);
+            return;
+        }
+    }
+
+    let model = game.model.as_ref().unwrap();
+    if model.is_over() || model.active_player != chain_player {
+        return;
+    }
+    let action = if !model.has_rolled() {
+        if !keys.just_pressed(KeyCode::Space) {
+            return;
+        }
+        ChainAction::Roll
+    } else if model.legal_moves().is_empty() {
+        // Like ending a local turn, the contract can't tell that the dice left can't be played
+        if !keys.just_pressed(KeyCode::Enter) {
+            return;
+        }
+        ChainAction::Pass
+    } else {
+        let Some(action) = clicked_move(model, &mouse, &windows, &cameras).and_then(|mv| ChainAction::for_move(model, &mv)) else {
+            return;
+        };
+        action
+    };
+    task_executor.start(backend.gdk.play(action));
+}
+
+// Ranks the turns for the dice left with the opponent's evaluator, or the best one available
+fn rank_hints(game: &Game) -> Option<Hint> {
+    let model = game.model.as_ref().unwrap();
+    if model.is_over() || !model.has_rolled() {
+        return None;
+    }
+    let fallback: Box<dyn Evaluator>;
+    let evaluator = match game.bot.as_ref() {
+        Some(bot) => bot.evaluator(),
+        None => {
+            fallback = best_available_evaluator();
+            fallback.as_ref()
+        }
+    };
+    let mut turns = Search::new(evaluator, SearchConfig::default()).rank_turns(&model.board, model.active_player, &model.dice);
+    turns.truncate(HINT_KEYS.len());
+    Some(Hint{
+        key: model.zobrist(),
+        turns,
+        preview: None
+    })
+}
+
+fn select_hint(game: &mut Game, index: usize) {
+    let Some(hint) = game.current_hint() else {
+        return;
+    };
+    let Some((turn, _)) = hint.turns.get(index) else {
+        return;
+    };
+    if hint.preview != Some(index) {
+        game.hint.as_mut().unwrap().preview = Some(index);
+        return;
+    }
+    let turn = turn.clone();
+    let model = game.model.as_mut().unwrap();
+    for mv in turn.moves {
+        if let Err(error) = model.play_move(mv) {
+            println!("Can't play the hint: {}", error);
+            break;
+        }
+    }
+    game.hint = None;
+}
+
+// The computer rolls and plays its turns one step at a time, waiting a little so they can be followed
+pub fn bot_turn(
+    time: Res<Time>,
+    mut waited: Local<f32>,
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let Some(bot) = game.bot.as_ref() else {
+        return;
+    };
+    let model = game.model.as_ref().unwrap();
+    if model.is_over() || model.active_player != bot.player {
+        *waited = 0.;
+        return;
+    }
+    *waited += time.delta_seconds();
+    if *waited < BOT_STEP_SECONDS {
+        return;
+    }
+    *waited = 0.;
+
+    let game = &mut *game;
+    let bot = game.bot.as_ref().unwrap();
+    let model = game.model.as_mut().unwrap();
+    if model.has_rolled() {
+        let turn = bot.choose_turn(model);
+        if let Err(error) = model.play_turn(&turn) {
+            println!("Computer can't play: {}", error);
+        }
+    } else if bot.should_double(model) && model.offer_double().is_ok() {
+        next_state.set(GameState::DoubleOffered);
+    } else if let Err(error) = model.roll_dice(random_roll(&mut rand::thread_rng())) {
+        println!("Computer can't roll: {}", error);
+    }
+}
+
+// The opponent of the doubling player answers with T to take or P to pass, the computer answers on its own
+pub fn double_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let model = game.model.as_ref().unwrap();
+    let answering_bot = game.bot.as_ref().filter(|bot| model.cube.offered_by.map_or(false, |offered_by| offered_by != bot.player));
+    let answer = if let Some(bot) = answering_bot {
+        if bot.should_take(model) {
+            GameModel::take_double
+        } else {
+            GameModel::drop_double
+        }
+    } else if keys.just_pressed(KeyCode::KeyT) {
+        GameModel::take_double
+    } else if keys.just_pressed(KeyCode::KeyP) {
+        GameModel::drop_double
+    } else {
+        return;
+    };
+
+    if answer(game.model.as_mut().unwrap()).is_ok() {
+        next_state.set(GameState::InGame);
+    }
+}
+
+fn cursor_position(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<Vec2> {
+    let window = windows.get_single().ok()?;
+    let (camera, camera_transform) = cameras.get_single().ok()?;
+    let cursor = window.cursor_position()?;
+    camera.viewport_to_world_2d(camera_transform, cursor)
+}
+
+// A decided game is scored in the match before its result is shown
+pub fn check_game_over(
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !game.model.as_ref().unwrap().is_over() {
+        return;
+    }
+    let game = &mut *game;
+    if let Some(current_match) = game.current_match.as_mut() {
+        current_match.record_game(game.model.as_ref().unwrap());
+    }
+    next_state.set(GameState::GameOver);
+}
+
+// The analysis searches every decision of the game, so it runs off the frame and the review opens once it's done
+pub fn game_over_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut analysis_task: Local<Option<Task<Analysis>>>,
+) {
+    if let Some(task) = analysis_task.as_mut() {
+        if let Some(analysis) = block_on(future::poll_once(task)) {
+            *analysis_task = None;
+            game.review = Some(Review{
+                analysis,
+                selected: 0
+            });
+            next_state.set(GameState::Review);
+            return;
+        }
+    }
+    if keys.just_pressed(KeyCode::KeyR) && analysis_task.is_none() {
+        let history = game.model.as_ref().unwrap().history.clone();
+        let evaluator: Arc<dyn Evaluator> = match game.bot.as_ref() {
+            Some(bot) => bot.shared_evaluator(),
+            None => Arc::from(best_available_evaluator())
+        };
+        *analysis_task = Some(AsyncComputeTaskPool::get().spawn(async move {
+            analyze(&history, evaluator.as_ref(), SearchConfig::default())
+        }));
+        println!("Analysing the game...");
+        return;
+    }
+    if !keys.just_pressed(KeyCode::Space) {
+        return;
+    }
+    // Dropping the task cancels an analysis still running
+    *analysis_task = None;
+    let match_continues = game.current_match.as_ref().map_or(false, |current_match| !current_match.is_over());
+    if match_continues {
+        game.start_next_game();
+        next_state.set(GameState::InGame);
+    } else {
+        game.current_match = None;
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+// Decisions are picked by clicking them in the list or with the arrow keys, Escape goes back to the result
+pub fn review_input(
+    keys: Res<ButtonInput<KeyCode>>,
+    interaction_query: Query<(&Id, &Interaction), (Changed<Interaction>, With<Button>)>,
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::GameOver);
+        return;
+    }
+
+    let Some(review) = game.review.as_ref() else {
+        return;
+    };
+    let count = review.analysis.decisions.len();
+    let mut selected = review.selected;
+    if keys.just_pressed(KeyCode::ArrowDown) && selected + 1 < count {
+        selected += 1;
+    }
+    if keys.just_pressed(KeyCode::ArrowUp) && selected > 0 {
+        selected -= 1;
+    }
+    for (id, interaction) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let Some(index) = id.id.strip_prefix("review_").and_then(|index| index.parse().ok()) {
+            selected = index;
+        }
+    }
+    if selected != review.selected {
+        game.review.as_mut().unwrap().selected = selected;
+    }
+}
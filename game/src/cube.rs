@@ -0,0 +1,47 @@
+use crate::utils::opponent;
+
+// https://www.bkgm.com/rules.html#doubling
+pub const MAX_CUBE_VALUE: u32 = 64;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Cube {
+    pub value: u32,
+    // Player holding the cube, `None` while it sits in the middle and either side may double
+    pub owner: Option<usize>,
+    // Player waiting for an answer to a double
+    pub offered_by: Option<usize>,
+    // The cube is out of play during the Crawford game of a match
+    pub enabled: bool
+}
+
+impl Default for Cube {
+    fn default() -> Cube {
+        Cube{
+            value: 1,
+            owner: None,
+            offered_by: None,
+            enabled: true
+        }
+    }
+}
+
+impl Cube {
+    pub fn can_double(&self, player: usize) -> bool {
+        self.enabled
+            && self.offered_by.is_none()
+            && self.owner.is_none_or(|owner| owner == player)
+            && self.value < MAX_CUBE_VALUE
+    }
+
+    pub fn offer(&mut self, player: usize) {
+        self.offered_by = Some(player);
+    }
+
+    // The player who takes owns the cube at twice the value
+    pub fn take(&mut self) {
+        if let Some(offered_by) = self.offered_by.take() {
+            self.value *= 2;
+            self.owner = Some(opponent(offered_by));
+        }
+    }
+}
@@ -4,17 +4,88 @@ use std::{any::Any, borrow::{Borrow, BorrowMut}};
 use bevy::{prelude::*, render::camera::ScalingMode, sprite::Anchor, window::{PresentMode, WindowMode, WindowTheme}};
 use bevy_simple_text_input::{TextInput, TextInputPlugin, TextInputSubmitEvent};
 
-use crate::utils::{global_to_player_tower_index, player_to_global_tower_index, Board, PLAYER_GUEST, PLAYER_HOST, TOWERS_COUNT,initialize};
-use crate::resources::{Backend,Game};
+use crate::utils::{player_to_global_tower_index, to_player_tower_index, Board, BAR_INDEX, PLAYER_GUEST, PLAYER_HOST, TOWERS_COUNT,initialize};
+use crate::resources::{Backend,Game,Hint};
+use crate::components::BoardPiece;
+use crate::cube::Cube;
+use crate::model::GameModel;
+use crate::match_play::Match;
+use crate::ui::build_review_panel;
+use crate::utils::turn_notation;
 
 const BAR_WIDTH : f32 = 100.;
+const BORNE_OFF_SPACING : f32 = 12.;
+const BAR_SPACING : f32 = 30.;
+const TOWER_WIDTH : f32 = 75.;
+const STATUS_FONT_SIZE : f32 = 18.;
+const CUBE_FONT_SIZE : f32 = 40.;
 
 
 pub fn setup_game(mut commands: Commands,mut game: ResMut<Game>){
     draw_points(commands.borrow_mut(), game.wooden_stack_texture.clone(), game.white_stack_texture.clone());
 
-    let board = game.board.as_ref().unwrap();
-    draw_nuts(commands.borrow_mut(), game.wooden_nut_texture.clone(), game.white_nut_texture.clone(),board);    
+    draw_pieces(commands.borrow_mut(), &game);
+}
+
+// Nuts, cube and status follow the game model, so they are redrawn whenever it changes
+pub fn update_board(mut commands: Commands,game: Res<Game>,pieces: Query<Entity, With<BoardPiece>>){
+    if !game.is_changed() {
+        return;
+    }
+    for entity in &pieces {
+        commands.entity(entity).despawn();
+    }
+    draw_pieces(commands.borrow_mut(), &game);
+}
+
+pub fn setup_review(mut commands: Commands,game: Res<Game>){
+    draw_points(commands.borrow_mut(), game.wooden_stack_texture.clone(), game.white_stack_texture.clone());
+
+    draw_review(commands.borrow_mut(), &game);
+}
+
+// The review shows the board of the selected decision next to the list of decisions
+pub fn update_review(mut commands: Commands,game: Res<Game>,pieces: Query<Entity, With<BoardPiece>>){
+    if !game.is_changed() {
+        return;
+    }
+    for entity in &pieces {
+        commands.entity(entity).despawn_recursive();
+    }
+    draw_review(commands.borrow_mut(), &game);
+}
+
+fn draw_review(commands: &mut Commands,game: &Game){
+    let Some(review) = game.review.as_ref() else {
+        return;
+    };
+    if let Some(decision) = review.analysis.decisions.get(review.selected) {
+        let board = &decision.board;
+        draw_nuts(commands, game.wooden_nut_texture.clone(), game.white_nut_texture.clone(),board);
+        draw_bar(commands, game.wooden_nut_texture.clone(), game.white_nut_texture.clone(),board);
+        draw_borne_off(commands, game.wooden_nut_texture.clone(), game.white_nut_texture.clone(),board);
+    }
+    build_review_panel(commands, review, game.lato_regular_font.clone());
+}
+
+fn draw_pieces(commands: &mut Commands,game: &Game){
+    let model = game.model.as_ref().unwrap();
+    let hint = game.current_hint();
+    // A previewed hint is drawn as if it had been played
+    let preview = hint.and_then(|hint| hint.preview.map(|index| &hint.turns[index].0)).map(|turn| {
+        let mut board = model.board.clone();
+        board.apply_turn(model.active_player, turn);
+        board
+    });
+    let board = preview.as_ref().unwrap_or(&model.board);
+    draw_nuts(commands, game.wooden_nut_texture.clone(), game.white_nut_texture.clone(),board);    
+    draw_bar(commands, game.wooden_nut_texture.clone(), game.white_nut_texture.clone(),board);
+    draw_borne_off(commands, game.wooden_nut_texture.clone(), game.white_nut_texture.clone(),board);
+    draw_cube(commands, game.lato_regular_font.clone(), &model.cube);
+    draw_status(commands, game.lato_regular_font.clone(), model, game.current_match.as_ref(), game.bot.as_ref().map(|bot| bot.player));
+    if let Some(hint) = hint {
+        draw_hint(commands, game.lato_regular_font.clone(), hint, model.active_player);
+    }
 }
 
 fn draw_points(commands:&mut Commands,wooden_stack_texture: Handle<Image>,white_stack_texture: Handle<Image>){
@@ -105,7 +176,6 @@ fn get_nut_position(global_tower_index: usize,nut_index: usize ) -> Vec3{
     let mut local_index = global_tower_index % 6;
     let is_right = (global_tower_index  / 6) % 2;
     let dir = ((global_tower_index / 12) as f32 - 1.) * -1.;
-    println!("{}",local_index);
     if is_right == 0 {
         local_index = 5 - local_index;
     }    
@@ -143,9 +213,189 @@ fn draw_nuts(commands: &mut Commands,wooden_nut_texture: Handle<Image>,white_nut
                 ..default()
             };
             
-            commands.spawn(sprite);
+            commands.spawn((sprite, BoardPiece));
         }
         
     }
         
 }
+
+// Borne off nuts are stacked beside the home board they left, the host's at the top right
+fn get_borne_off_position(player: usize, nut_index: usize) -> Vec3{
+    let x = (BAR_WIDTH * 0.5) + 6. * 75. + 40.;
+    if player == PLAYER_HOST {
+        Vec3::from_array([x, 720. - 85. - (nut_index as f32 * BORNE_OFF_SPACING), 0.])
+    }else{
+        Vec3::from_array([x, nut_index as f32 * BORNE_OFF_SPACING - 10., 0.])
+    }
+}
+
+fn draw_borne_off(commands: &mut Commands,wooden_nut_texture: Handle<Image>,white_nut_texture: Handle<Image> , board: &Board){
+    for player in [PLAYER_HOST, PLAYER_GUEST]{
+        let texture_handle = if player == PLAYER_HOST {
+            wooden_nut_texture.clone()
+        } else{
+            white_nut_texture.clone()
+        };
+
+        for nut_index in 0..board.borne_off.nuts(player){
+            let sprite = SpriteBundle{
+                texture: texture_handle.clone() ,
+                transform: Transform::from_translation(get_borne_off_position(player, nut_index as usize)),
+                sprite: Sprite{
+                    anchor : Anchor::BottomCenter,
+                    ..default()
+                },
+                ..default()
+            };
+
+            commands.spawn((sprite, BoardPiece));
+        }
+    }
+}
+
+// Hit nuts wait on the bar next to the side they enter from
+fn get_bar_position(player: usize, nut_index: usize) -> Vec3{
+    if player == PLAYER_HOST {
+        Vec3::from_array([0., 300. - (nut_index as f32 * BAR_SPACING), 0.])
+    }else{
+        Vec3::from_array([0., 420. + (nut_index as f32 * BAR_SPACING), 0.])
+    }
+}
+
+fn draw_bar(commands: &mut Commands,wooden_nut_texture: Handle<Image>,white_nut_texture: Handle<Image> , board: &Board){
+    for player in [PLAYER_HOST, PLAYER_GUEST]{
+        let texture_handle = if player == PLAYER_HOST {
+            wooden_nut_texture.clone()
+        } else{
+            white_nut_texture.clone()
+        };
+
+        for nut_index in 0..board.bar.nuts(player){
+            let sprite = SpriteBundle{
+                texture: texture_handle.clone() ,
+                transform: Transform::from_translation(get_bar_position(player, nut_index as usize)),
+                ..default()
+            };
+
+            commands.spawn((sprite, BoardPiece));
+        }
+    }
+}
+
+// The cube sits in the middle of the bar until someone owns it, then moves to the owner's side
+fn draw_cube(commands: &mut Commands,font: Handle<Font>,cube: &Cube){
+    let y = match cube.owner {
+        None => 360.,
+        Some(PLAYER_HOST) => 720. - 40.,
+        Some(_) => 40.
+    };
+    let text = Text2dBundle{
+        text: Text::from_section(cube.value.to_string(), TextStyle{
+            font,
+            font_size: CUBE_FONT_SIZE,
+            color: Color::WHITE
+        }),
+        transform: Transform::from_xyz(0., y, 1.),
+        ..default()
+    };
+    commands.spawn((text, BoardPiece));
+}
+
+pub fn player_name(player: usize) -> &'static str {
+    if player == PLAYER_HOST {
+        "Host"
+    }else{
+        "Guest"
+    }
+}
+
+fn match_text(current_match: &Match) -> String{
+    let crawford = if current_match.is_crawford { "\nCrawford game" } else { "" };
+    format!("Match to {}\nHost {} - Guest {}{}\n\n", current_match.length, current_match.score[0], current_match.score[1], crawford)
+}
+
+fn status_text(model: &GameModel,bot_player: Option<usize>) -> String{
+    let player = player_name(model.active_player);
+    if let Some(result) = model.result {
+        return format!("{} wins\n{} point(s)", player_name(result.winner), model.points().unwrap());
+    }
+    if let Some(offered_by) = model.cube.offered_by {
+        return format!("{} doubles to {}\nT: take\nP: pass", player_name(offered_by), model.cube.value * 2);
+    }
+    // The computer's dice are shown without the keys a human would use
+    if bot_player == Some(model.active_player) {
+        return match model.roll {
+            None => format!("{} (computer) to roll", player),
+            Some(roll) => format!("{} (computer) rolled {}-{}", player, roll[0], roll[1])
+        };
+    }
+    match model.roll {
+        None if model.cube.enabled => format!("{} to roll\nSpace: roll\nD: double\nI: print id", player),
+        None => format!("{} to roll\nSpace: roll\nI: print id", player),
+        Some(roll) => format!("{} rolled {}-{}\nClick: move\nBackspace: undo\nEnter: end turn\nH: hint", player, roll[0], roll[1])
+    }
+}
+
+fn draw_status(commands: &mut Commands,font: Handle<Font>,model: &GameModel,current_match: Option<&Match>,bot_player: Option<usize>){
+    let status = match current_match {
+        Some(current_match) => match_text(current_match) + &status_text(model, bot_player),
+        None => status_text(model, bot_player)
+    };
+    let text = Text2dBundle{
+        text: Text::from_section(status, TextStyle{
+            font,
+            font_size: STATUS_FONT_SIZE,
+            color: Color::WHITE
+        }),
+        text_anchor: Anchor::CenterLeft,
+        transform: Transform::from_xyz(-630., 360., 1.),
+        ..default()
+    };
+    commands.spawn((text, BoardPiece));
+}
+
+fn hint_text(hint: &Hint,player: usize) -> String{
+    let mut lines = vec!["Hints, number to preview, again to play".to_string()];
+    for (index, (turn, equity)) in hint.turns.iter().enumerate() {
+        let marker = if hint.preview == Some(index) { ">" } else { " " };
+        lines.push(format!("{}{}. {} {:+.3}", marker, index + 1, turn_notation(player, turn), equity));
+    }
+    lines.join("\n")
+}
+
+fn draw_hint(commands: &mut Commands,font: Handle<Font>,hint: &Hint,player: usize){
+    let text = Text2dBundle{
+        text: Text::from_section(hint_text(hint, player), TextStyle{
+            font,
+            font_size: STATUS_FONT_SIZE,
+            color: Color::WHITE
+        }),
+        text_anchor: Anchor::TopLeft,
+        transform: Transform::from_xyz(-630., 200., 1.),
+        ..default()
+    };
+    commands.spawn((text, BoardPiece));
+}
+
+// Inverse of `get_nut_position`: the global tower under a world position, or `BAR_INDEX` for the bar
+pub fn get_tower_at(position: Vec2) -> Option<usize>{
+    // Towers are centered TOWER_WIDTH apart, starting at the edge of the bar
+    let distance = position.x.abs() - (BAR_WIDTH * 0.5);
+    if distance < -(TOWER_WIDTH * 0.5) {
+        return Some(BAR_INDEX);
+    }
+    let local_index = (distance / TOWER_WIDTH).round().max(0.) as usize;
+    if local_index > 5 {
+        return None;
+    }
+
+    let is_bottom = position.y < 360.;
+    let global_tower_index = match (is_bottom, position.x > 0.) {
+        (true, true) => 5 - local_index,
+        (true, false) => 6 + local_index,
+        (false, false) => 17 - local_index,
+        (false, true) => 18 + local_index
+    };
+    Some(global_tower_index)
+}
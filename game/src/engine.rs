@@ -0,0 +1,190 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use crate::bot::Bot;
+use crate::gnubg::{board_from_position_id, position_id};
+use crate::model::GameModel;
+use crate::utils::{move_notation, roll_to_dice, turn_notation, Board, Turn, PLAYER_HOST};
+
+/*
+ * Line based protocol to play against engines running as child processes, in the spirit of chess's UCI.
+ *
+ *   client                                  engine
+ *   bgp                                     id name <name>, then bgpok
+ *   isready                                 readyok
+ *   newgame
+ *   position <PositionID> dice <die> <die>
+ *   go                                      bestmove <move>
+ *   quit
+ *
+ * The Position ID is the gnubg one seen by the player on roll, and the move is written in standard
+ * notation from their side without "(2)" shorthands, e.g. "bestmove 8/5 6/5" or "bestmove bar/22 13/11*",
+ * and "bestmove none" when no nut can move. Engines may send "info <text>" lines at any time,
+ * they are ignored like every line the other side doesn't know
+ */
+
+// Time a quitting engine gets to exit before it's killed
+const QUIT_GRACE: Duration = Duration::from_secs(1);
+const QUIT_POLL: Duration = Duration::from_millis(50);
+
+// An engine started as a child process, asked for moves over its standard input and output
+pub struct EngineClient {
+    name: String,
+    child: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>
+}
+
+impl EngineClient {
+    // Starts `program` and waits for it to introduce itself
+    pub fn spawn(program: &str, args: &[&str]) -> io::Result<EngineClient> {
+        let mut child = Command::new(program).args(args).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+        let mut engine = EngineClient{
+            name: program.to_string(),
+            child,
+            input,
+            output
+        };
+        engine.send("bgp")?;
+        loop {
+            let line = engine.read_line()?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            } else if line == "bgpok" {
+                return Ok(engine);
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_ready(&mut self) -> io::Result<()> {
+        self.send("isready")?;
+        while self.read_line()? != "readyok" {}
+        Ok(())
+    }
+
+    pub fn new_game(&mut self) -> io::Result<()> {
+        self.send("newgame")?;
+        self.is_ready()
+    }
+
+    // The engine's turn for `player` with `roll`, an error when it isn't a legal one
+    pub fn choose_turn(&mut self, board: &Board, player: usize, roll: [u8;2]) -> io::Result<Turn> {
+        self.send(&format!("position {} dice {} {}", position_id(board, player), roll[0], roll[1]))?;
+        self.send("go")?;
+        let notation = loop {
+            if let Some(notation) = self.read_line()?.strip_prefix("bestmove") {
+                break notation.trim().to_string();
+            }
+        };
+        parse_turn(board, player, &roll_to_dice(roll), &notation).ok_or_else(|| {
+            io::Error::new(ErrorKind::InvalidData, format!("{} played an illegal move: {}", self.name, notation))
+        })
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.input, "{}", line)?;
+        self.input.flush()
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.output.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, format!("{} stopped answering", self.name)));
+        }
+        Ok(line.trim_end().to_string())
+    }
+}
+
+impl Drop for EngineClient {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        for _ in 0..QUIT_GRACE.as_millis() / QUIT_POLL.as_millis() {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(QUIT_POLL);
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// The legal turn written as `notation` by `player`, in any move order and with or without hit marks
+pub fn parse_turn(board: &Board, player: usize, dice: &[u8], notation: &str) -> Option<Turn> {
+    let normalize = |moves: Vec<String>| {
+        let mut moves: Vec<String> = moves.iter().map(|mv| mv.trim_end_matches('*').to_ascii_lowercase()).collect();
+        moves.sort();
+        moves
+    };
+    let wanted = if notation == "none" {
+        vec![]
+    } else {
+        normalize(notation.split_whitespace().map(str::to_string).collect())
+    };
+    board.legal_turns(player, dice).into_iter().find(|turn| {
+        normalize(turn.moves.iter().map(|mv| move_notation(player, mv)).collect()) == wanted
+    })
+}
+
+// Answers the protocol read from `input` with `bot` choosing the moves, until "quit" or the end of the input
+pub fn serve<R: BufRead, W: Write>(name: &str, bot: &Bot, input: R, mut output: W) -> io::Result<()> {
+    let mut model: Option<GameModel> = None;
+    for line in input.lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("bgp") => {
+                writeln!(output, "id name {}", name)?;
+                writeln!(output, "bgpok")?;
+            },
+            Some("isready") => writeln!(output, "readyok")?,
+            Some("newgame") => model = None,
+            Some("position") => {
+                model = match parse_position(words.collect()) {
+                    Ok(position) => Some(position),
+                    Err(error) => {
+                        writeln!(output, "info invalid position: {}", error)?;
+                        None
+                    }
+                };
+            },
+            Some("go") => {
+                let notation = match model.as_ref() {
+                    Some(model) => match bot.choose_turn(model) {
+                        turn if turn.moves.is_empty() => "none".to_string(),
+                        turn => turn_notation(model.active_player, &turn)
+                    },
+                    None => "none".to_string()
+                };
+                writeln!(output, "bestmove {}", notation)?;
+            },
+            Some("quit") => break,
+            _ => ()
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
+// "<PositionID> dice <die> <die>", with the engine playing the host
+fn parse_position(words: Vec<&str>) -> Result<GameModel, String> {
+    let [id, "dice", first, second] = words[..] else {
+        return Err("expected <PositionID> dice <die> <die>".to_string());
+    };
+    let die = |text: &str| text.parse::<u8>().ok().filter(|die| (1..=6).contains(die)).ok_or(format!("invalid die {}", text));
+    let roll = [die(first)?, die(second)?];
+
+    let mut model = GameModel::new();
+    model.board = board_from_position_id(id, PLAYER_HOST).map_err(|error| error.to_string())?;
+    model.active_player = PLAYER_HOST;
+    model.roll_dice(roll).map_err(|error| error.to_string())?;
+    Ok(model)
+}
@@ -0,0 +1,14 @@
+use aptos_sdk::types::account_address::AccountAddress;
+use bevy::prelude::*;
+
+// A player of the on-chain game played their last die, the board is behind until the game is read again
+#[derive(Event)]
+pub struct EndOfTurnEvent{
+    pub player: AccountAddress
+}
+
+// A player of the on-chain game forfeited it
+#[derive(Event)]
+pub struct GameOverEvent{
+    pub player: AccountAddress
+}
@@ -5,16 +5,24 @@ use std::{any::Any, borrow::{Borrow, BorrowMut}};
 use bevy::{prelude::*, render::camera::ScalingMode, sprite::Anchor, window::{PresentMode, WindowMode, WindowTheme}};
 use bevy_simple_text_input::{TextInput, TextInputPlugin, TextInputSubmitEvent};
 
-use crate::gdk::GDK;
-use crate::utils::{global_to_player_tower_index, player_to_global_tower_index, Board, PLAYER_GUEST, PLAYER_HOST, TOWERS_COUNT,initialize};
+use anyhow::Result;
 
-use crate::drawing::setup_game;
-use crate::ui::{setup_menu,update_mainmenu,input_listener};
-use crate::resources::{Backend,Game};
+use crate::gdk::{GDK,GameEvent,OnChainGame};
+use crate::utils::{opponent, GameResult, ResultType, player_to_global_tower_index, to_player_tower_index, Board, PLAYER_GUEST, PLAYER_HOST, TOWERS_COUNT};
+use crate::model::GameModel;
+
+use crate::drawing::{setup_game,update_board,setup_review,update_review};
+use crate::controls::{chain_game_input,game_input,bot_turn,double_input,check_game_over,game_over_input,review_input};
+use crate::ui::{setup_menu,update_mainmenu,update_text_buttons,input_listener,setup_game_over,setup_wallet,wallet_input,update_funding,setup_funding_failed,funding_failed_input};
+use crate::match_play::DEFAULT_MATCH_LENGTH;
+use crate::resources::{Backend,ChainWatcher,Game};
+use crate::events::{EndOfTurnEvent,GameOverEvent};
 use crate::components::{ButtonAssets,Id};
 use crate::states::GameState;
 
 use bevy_async_task::{AsyncTaskRunner, AsyncTaskStatus};
+use aptos_sdk::types::account_address::AccountAddress;
+use std::sync::Mutex;
 
 
 fn load_assets(mut commands: Commands,asset_server: Res<AssetServer>,mut game: ResMut<Game>){
@@ -43,9 +51,8 @@ fn load_assets(mut commands: Commands,asset_server: Res<AssetServer>,mut game: R
 
     commands.spawn(camera);
 
-    let board = initialize();        
-
-    game.board =Some(board);
+    game.model = Some(GameModel::new());
+    game.match_length = DEFAULT_MATCH_LENGTH;
     game.host_button_assets = Some(host_button_assets);
     game.join_button_assets = Some(join_button_assets);
     game.lato_regular_font = lato_regular_font;
@@ -55,37 +62,125 @@ fn load_assets(mut commands: Commands,asset_server: Res<AssetServer>,mut game: R
     game.white_nut_texture = white_nut_texture;
 }
 
-fn create_game(mut backend: ResMut<Backend>,mut task_executor: AsyncTaskRunner<u32>){
+// The game hosted or joined is only known once its transaction is executed
+fn forget_chain_game(mut backend: ResMut<Backend>){
+    backend.gdk.game_address = None;
+}
+
+// Hosts a game, or joins the one typed in the main menu, until the GDK knows its address
+fn start_chain_game(state: Res<State<GameState>>,mut backend: ResMut<Backend>,game: Res<Game>,mut next_state: ResMut<NextState<GameState>>,mut task_executor: AsyncTaskRunner<Result<AccountAddress>>){
     match task_executor.poll() {
         AsyncTaskStatus::Idle => {
-            // Start an async task!
-            task_executor.start(backend.gdk.create_game());
-            // Closures also work:
-            // task_executor.start(async { 5 });
-            println!("Creating game!");
+            if backend.gdk.game_address.is_some() {
+                return;
+            }
+            match (state.get(), game.host_addr) {
+                (GameState::JoiningGame, Some(host_addr)) => task_executor.start(backend.gdk.join_game(host_addr)),
+                (GameState::JoiningGame, None) => next_state.set(GameState::MainMenu),
+                _ => task_executor.start(backend.gdk.create_game())
+            }
         }
-        AsyncTaskStatus::Pending => {
-            println!("Creating game, pending!");
+        AsyncTaskStatus::Pending => (),
+        AsyncTaskStatus::Finished(Ok(game_address)) => {
+            println!("Playing the game at {}", game_address);
+            backend.gdk.game_address = Some(game_address);
+        }
+        AsyncTaskStatus::Finished(Err(error)) => {
+            println!("{:#}", error);
+            next_state.set(GameState::MainMenu);
+        }
+    }
+}
 
+// Once the game is created or joined, the board shows it as the contract holds it
+fn load_chain_game(mut commands: Commands,backend: Res<Backend>,mut game: ResMut<Game>,mut next_state: ResMut<NextState<GameState>>,mut task_executor: AsyncTaskRunner<Result<OnChainGame>>){
+    match task_executor.poll() {
+        AsyncTaskStatus::Idle => {
+            if backend.gdk.game_address.is_some() {
+                task_executor.start(backend.gdk.fetch_game());
+            }
         }
-        AsyncTaskStatus::Finished(v) => {
-            println!("Game created!");
+        AsyncTaskStatus::Pending => (),
+        AsyncTaskStatus::Finished(Ok(chain_game)) => {
+            game.bot = None;
+            game.current_match = None;
+            game.chain_player = chain_game.seat_of(backend.gdk.player_address());
+            game.model = Some(chain_game.to_model());
+            match backend.gdk.watch_events() {
+                Ok((events, task)) => commands.insert_resource(ChainWatcher{ events: Mutex::new(events), task }),
+                Err(error) => println!("{:#}", error)
+            }
+            next_state.set(GameState::InGame);
+        }
+        AsyncTaskStatus::Finished(Err(error)) => {
+            println!("{:#}", error);
+            next_state.set(GameState::MainMenu);
         }
     }
 }
 
+fn forward_chain_events(watcher: Res<ChainWatcher>,mut end_of_turn: EventWriter<EndOfTurnEvent>,mut game_over: EventWriter<GameOverEvent>){
+    for event in watcher.events.lock().unwrap().try_iter() {
+        match event {
+            GameEvent::EndOfTurn{ player } => { end_of_turn.send(EndOfTurnEvent{ player }); },
+            GameEvent::GameOver{ player } => { game_over.send(GameOverEvent{ player }); }
+        }
+    }
+}
 
-// fn create_game(mut backend: ResMut<Backend>){
-//     let create_game_future = backend.gdk.create_game();
-    
-//     println!("Creating game...");
-// }
+// Reads the game again whenever a player ends their turn or forfeits, so the opponent's moves show up on their own.
+// `check_game_over` then moves to the game over screen
+fn refresh_chain_game(
+    backend: Res<Backend>,
+    mut game: ResMut<Game>,
+    mut end_of_turn: EventReader<EndOfTurnEvent>,
+    mut game_over: EventReader<GameOverEvent>,
+    // The board on screen is older than the last event
+    mut stale: Local<bool>,
+    mut forfeited_by: Local<Option<AccountAddress>>,
+    mut task_executor: AsyncTaskRunner<Result<OnChainGame>>
+){
+    *stale |= end_of_turn.read().count() > 0;
+    if let Some(event) = game_over.read().last() {
+        *forfeited_by = Some(event.player);
+        *stale = true;
+    }
+    match task_executor.poll() {
+        AsyncTaskStatus::Idle => {
+            if *stale {
+                *stale = false;
+                task_executor.start(backend.gdk.fetch_game());
+            }
+        }
+        AsyncTaskStatus::Pending => (),
+        AsyncTaskStatus::Finished(Ok(chain_game)) => {
+            let mut model = chain_game.to_model();
+            // The contract doesn't record who won a forfeited game, it's the player who didn't forfeit
+            if let Some(player) = forfeited_by.take() {
+                let loser = if chain_game.guest_player.as_ref().map(|guest| guest.owner) == Some(player) { PLAYER_GUEST } else { PLAYER_HOST };
+                model.result = model.result.or(Some(GameResult{
+                    winner: opponent(loser),
+                    result_type: ResultType::Single
+                }));
+            }
+            game.model = Some(model);
+        }
+        AsyncTaskStatus::Finished(Err(error)) => println!("{:#}", error)
+    }
+}
 
-fn join_game(mut backend: ResMut<Backend> , game: Res<Game> ){
-    _ = backend.gdk.join_game(game.host_addr.clone().unwrap());
-    println!("Joining game...");        
+// The on-chain game is left once it's over or the player goes back to the menu, its late events
+// mustn't reach the games played after it
+fn stop_chain_watcher(mut commands: Commands){
+    commands.remove_resource::<ChainWatcher>();
 }
 
+// Shows why on-chain games are unavailable right away instead of letting them fail on gas
+fn check_funding(backend: Res<Backend>, mut next_state: ResMut<NextState<GameState>>){
+    if backend.funding_error.is_some() {
+        next_state.set(GameState::FundingFailed);
+    }
+}
 
 // remove all entities that are not a camera or window
 fn tear_down(mut commands: Commands, entities: Query<Entity, (Without<Camera>, Without<Window>)>) {
@@ -97,6 +192,12 @@ fn tear_down(mut commands: Commands, entities: Query<Entity, (Without<Camera>, W
 pub async fn run_game() {    
     let gdk = GDK::new();        
     let wallet_address = gdk.get_address();
+    // On-chain games need gas, so the account is funded before the menu shows up
+    let funding = gdk.ensure_funded().await;
+    match funding.as_ref() {
+        Ok(balance) => println!("{} has {} octas", wallet_address, balance),
+        Err(error) => println!("{:#}", error)
+    }
     
 
     let mut app = App::new();
@@ -127,19 +228,46 @@ pub async fn run_game() {
     }),)    
     .init_resource::<Game>()
     .insert_resource(Backend{
-        gdk: gdk
+        gdk: gdk,
+        balance: funding.as_ref().ok().copied(),
+        funding_error: funding.err().map(|error| format!("{:#}", error)),
+        funding_pending: false
     })
-    .add_state::<GameState>()    
+    .add_state::<GameState>()
+    .add_event::<EndOfTurnEvent>()
+    .add_event::<GameOverEvent>()    
     .add_plugins(TextInputPlugin)
-    .add_systems(Startup, load_assets)
+    .add_systems(Startup, (load_assets, check_funding))
     .add_systems(OnEnter(GameState::InGame), setup_game.after(load_assets))
     .add_systems(OnExit(GameState::InGame),tear_down)
+    .add_systems(OnEnter(GameState::DoubleOffered), setup_game)
+    .add_systems(OnExit(GameState::DoubleOffered),tear_down)
+    .add_systems(OnEnter(GameState::GameOver), setup_game_over)
+    .add_systems(OnExit(GameState::GameOver),tear_down)
+    .add_systems(OnEnter(GameState::Review), setup_review)
+    .add_systems(OnExit(GameState::Review),tear_down)
+    .add_systems(OnEnter(GameState::FundingFailed), setup_funding_failed)
+    .add_systems(OnExit(GameState::FundingFailed),tear_down)
+    .add_systems(OnEnter(GameState::Wallet), setup_wallet)
+    .add_systems(OnExit(GameState::Wallet),tear_down)
     .add_systems(OnEnter(GameState::MainMenu), setup_menu.after(load_assets))        
     .add_systems(OnExit(GameState::MainMenu),tear_down)
-    .add_systems(OnEnter(GameState::CreatingGame),create_game)
-    .add_systems(OnEnter(GameState::JoiningGame),join_game)
+    .add_systems(OnEnter(GameState::CreatingGame),forget_chain_game)
+    .add_systems(OnEnter(GameState::JoiningGame),forget_chain_game)
+    .add_systems(Update, (start_chain_game, load_chain_game).chain().run_if(in_state(GameState::CreatingGame).or_else(in_state(GameState::JoiningGame))))
     .add_systems(Update, update_mainmenu.run_if(in_state(GameState::MainMenu)))
+    .add_systems(Update, update_text_buttons.run_if(in_state(GameState::MainMenu)))
     .add_systems(Update, input_listener.run_if(in_state(GameState::MainMenu)))
+    .add_systems(OnEnter(GameState::MainMenu), stop_chain_watcher)
+    .add_systems(OnEnter(GameState::GameOver), stop_chain_watcher)
+    .add_systems(Update, forward_chain_events.run_if(resource_exists::<ChainWatcher>))
+    .add_systems(Update, (refresh_chain_game.run_if(resource_exists::<ChainWatcher>), game_input, chain_game_input, bot_turn, check_game_over, update_board).chain().run_if(in_state(GameState::InGame)))
+    .add_systems(Update, game_over_input.run_if(in_state(GameState::GameOver)))
+    .add_systems(Update, (review_input, update_review).chain().run_if(in_state(GameState::Review)))
+    .add_systems(Update, wallet_input.run_if(in_state(GameState::Wallet)))
+    .add_systems(Update, update_funding)
+    .add_systems(Update, funding_failed_input.run_if(in_state(GameState::FundingFailed)))
+    .add_systems(Update, (double_input, update_board).chain().run_if(in_state(GameState::DoubleOffered)))
     
     
     ;
@@ -1,22 +1,31 @@
-use std::{str::FromStr, time::{SystemTime, UNIX_EPOCH}};
+use std::{future::Future, str::FromStr, sync::{mpsc::{self, Receiver, Sender}, Arc}, time::{SystemTime, UNIX_EPOCH}};
 
 use aptos_sdk::{bcs, coin_client::CoinClient, rest_client::{Client, FaucetClient}, types::{account_config::chain_id, transaction::{RawTransaction, SignedTransaction}, LocalAccount}};
 use bevy::utils::tracing::instrument::WithSubscriber;
 use once_cell::sync::Lazy;
 use url::Url;
 use tokio;
-use anyhow::{Context, Result};
+use tokio::{runtime::Handle, task::JoinHandle};
+use anyhow::{bail, Context, Result};
 use aptos_sdk::move_types::ident_str;
 use aptos_sdk::move_types::identifier::Identifier;
 use aptos_sdk::move_types::language_storage::{ModuleId, TypeTag};
 use aptos_sdk::rest_client::aptos::AptosCoin;
 use aptos_sdk::rest_client::Transaction;
+use aptos_sdk::rest_client::aptos_api_types::{AptosErrorCode, Event};
+use aptos_sdk::rest_client::error::RestError;
 //use aptos_sdk::rest_client::aptos_api_types::TransactionPayload;
 use aptos_sdk::transaction_builder::{TransactionBuilder, TransactionFactory};
 use aptos_sdk::types::account_address::AccountAddress;
 use aptos_sdk::types::chain_id::ChainId;
-use aptos_sdk::types::transaction::{EntryFunction, TransactionPayload};
+use aptos_sdk::types::transaction::{EntryFunction, TransactionPayload, ViewFunction};
 use rand::rngs::OsRng;
+use serde::Deserialize;
+
+use crate::chain::ChainAction;
+use crate::model::GameModel;
+use crate::utils::{Board, PLAYER_GUEST, PLAYER_HOST};
+use crate::wallet;
 
 use std::time::{ Instant, Duration };
 
@@ -40,34 +49,188 @@ static FAUCET_URL: Lazy<Url> = Lazy::new(|| {
     .unwrap()
 });
 
-enum State{
-    None,    
-    Creating,
-    Created,
-    Joining,
-    Started,
+// Least balance, in octas, that pays for the gas of a game's transactions
+const MIN_BALANCE: u64 = 10_000_000;
+// What the faucet sends when the balance is below it
+const FUNDING_AMOUNT: u64 = 100_000_000;
+// How long checking and funding the account may take before the node is considered unreachable
+const FUNDING_TIMEOUT: Duration = Duration::from_secs(15);
+
+const DEFAULT_MAX_GAS: u64 = 5_000;
+// Entry functions using randomness must be able to pay the gas they declare, 56789 for `roll_the_dice`
+const RANDOMNESS_MAX_GAS: u64 = 60_000;
+const GAS_UNIT_PRICE: u64 = 100;
+const TRANSACTION_TIMEOUT_SECONDS: u64 = 10;
+
+// How often the event watcher looks for new transactions of the players
+const EVENT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const TRANSACTIONS_PER_POLL: u16 = 25;
+
+// Only local and dev networks have a faucet
+fn has_faucet() -> bool {
+    let host = NODE_URL.host_str().unwrap_or("");
+    host == "127.0.0.1" || host == "localhost" || host.contains("devnet")
+}
+
+// A seat of an on-chain game, `kind` is the player index the contract gave it
+#[derive(Clone, Debug, Deserialize)]
+pub struct OnChainPlayer {
+    pub kind: u8,
+    pub owner: AccountAddress
+}
+
+// The contract's `Game` resource, its fields in the contract's order so it decodes from BCS as is.
+// The board uses global tower indices like `Board` does
+#[derive(Clone, Debug, Deserialize)]
+pub struct OnChainGame {
+    pub board: Board,
+    pub host_player: Option<OnChainPlayer>,
+    pub guest_player: Option<OnChainPlayer>,
+    // Dice left to play, from 0 to 5 like the contract draws them, see `dice`
+    pub active_player_dices: Vec<u8>,
+    pub active_player: u8,
+    pub is_game_over: bool,
+    pub is_game_started: bool
+}
+
+impl OnChainGame {
+    // Dice left to play, as pips from 1 to 6
+    pub fn dice(&self) -> Vec<u8> {
+        self.active_player_dices.iter().map(|die| die + 1).collect()
+    }
+
+    // The seat `address` plays in the game
+    pub fn seat_of(&self, address: AccountAddress) -> Option<usize> {
+        [(PLAYER_HOST, &self.host_player), (PLAYER_GUEST, &self.guest_player)]
+            .into_iter()
+            .find(|(_, player)| player.as_ref().is_some_and(|player| player.owner == address))
+            .map(|(seat, _)| seat)
+    }
+
+    pub fn active_player(&self) -> usize {
+        if self.active_player as usize == PLAYER_GUEST { PLAYER_GUEST } else { PLAYER_HOST }
+    }
+
+    // The position to draw and play on. The contract only keeps the dice left, so a roll partly played
+    // reads as those dice, one die left as a double of it
+    pub fn to_model(&self) -> GameModel {
+        let mut model = GameModel::new();
+        model.board = self.board.clone();
+        model.active_player = self.active_player();
+        model.dice = self.dice();
+        model.roll = match model.dice[..] {
+            [] => None,
+            [first, ..] => Some([first, *model.dice.last().unwrap()])
+        };
+        model.result = self.board.result();
+        model
+    }
+}
+
+// What the contract announces about a game, with the account whose transaction emitted it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GameEvent {
+    // `player` played their last die, their opponent is to roll
+    EndOfTurn{ player: AccountAddress },
+    // `player` forfeited the game, or won it bearing off their last nut
+    GameOver{ player: AccountAddress }
+}
+
+// The contract's entry function for `action`, its BCS encoded arguments and the gas it may use
+fn entry_call(action: ChainAction, game_address: AccountAddress) -> Result<(&'static str, Vec<Vec<u8>>, u64)> {
+    let game = bcs::to_bytes(&game_address)?;
+    Ok(match action {
+        ChainAction::Roll => ("roll_the_dice", vec![game], RANDOMNESS_MAX_GAS),
+        ChainAction::Move{ tower_index, dice_index } => ("choose_move", vec![game, bcs::to_bytes(&tower_index)?, bcs::to_bytes(&dice_index)?], DEFAULT_MAX_GAS),
+        ChainAction::BearOff{ player_tower_index, dice_index } => ("bear_off", vec![game, bcs::to_bytes(&player_tower_index)?, bcs::to_bytes(&dice_index)?], DEFAULT_MAX_GAS),
+        ChainAction::Pass => ("pass_turn", vec![game], DEFAULT_MAX_GAS),
+        ChainAction::Forfeit => ("forfeit", vec![game], DEFAULT_MAX_GAS)
+    })
+}
+
+// Everything sending a transaction needs, owned so it can be sent from a task
+struct Submitter {
+    rest_client: Client,
+    transaction_factory: TransactionFactory,
+    module_id: ModuleId,
+    account: Arc<LocalAccount>
+}
+
+impl Submitter {
+    // Signs and submits a call to the contract's entry function `function` with its BCS encoded arguments,
+    // waiting until it's executed
+    async fn submit(self, function: &'static str, args: Vec<Vec<u8>>, max_gas_amount: u64) -> Result<Transaction> {
+        // The account may have sent transactions in an earlier run, or one of ours may have failed
+        if let Err(error) = self.refresh_sequence_number().await {
+            println!("{:#}", error);
+        }
+        let entry_function = EntryFunction::new(self.module_id.clone(), Identifier::new(function)?, vec![], args);
+        let raw_transaction = self.transaction_factory
+            .entry_function(entry_function)
+            .sender(self.account.address())
+            .sequence_number(self.account.sequence_number())
+            .max_gas_amount(max_gas_amount)
+            .gas_unit_price(GAS_UNIT_PRICE)
+            .expiration_timestamp_secs(SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+                + TRANSACTION_TIMEOUT_SECONDS
+            )
+            .build();
+        let signed_transaction = self.account.sign_transaction(raw_transaction);
+        let transaction = self.rest_client
+            .submit_and_wait(&signed_transaction)
+            .await
+            .with_context(|| format!("{} failed", function))?
+            .into_inner();
+        self.account.increment_sequence_number();
+        Ok(transaction)
+    }
+
+    async fn refresh_sequence_number(&self) -> Result<()> {
+        let account = self.rest_client
+            .get_account(self.account.address())
+            .await
+            .context("Failed to get the player's account")?
+            .into_inner();
+        self.account.set_sequence_number(account.sequence_number);
+        Ok(())
+    }
 }
 
 pub struct GDK{
     rest_client: Client,
-    faucet_client: FaucetClient,
+    faucet_client: Arc<FaucetClient>,
     //coin_client: CoinClient,
-    player_account: LocalAccount,
+    // Shared with the transactions sent as tasks, which sign with it and keep its sequence number
+    player_account: Arc<LocalAccount>,
+    // False for a temporary account made up when the key file couldn't be read or written
+    account_saved: bool,
     transaction_factory: TransactionFactory,
     module_id: ModuleId,
-    pub state: State,
+    // Runtime the event watchers are spawned on, Bevy's systems run outside of it
+    runtime: Handle,
     pub game_address: Option<AccountAddress>,
 }
 
 impl GDK {
     pub fn new() -> GDK{
         let rest_client = Client::new(NODE_URL.clone());
-        let faucet_client = FaucetClient::new(FAUCET_URL.clone(), NODE_URL.clone());        
+        let faucet_client = Arc::new(FaucetClient::new(FAUCET_URL.clone(), NODE_URL.clone()));        
         let coin_client = CoinClient::new(&rest_client);
                 
         // let mut alice = LocalAccount::generate(&mut OsRng);
         // let bob = LocalAccount::generate(&mut OsRng);
-        let player_account = LocalAccount::generate(&mut OsRng);
+        // A broken key file is left alone, the player can import their key again from the wallet screen.
+        // Until then they play with a temporary account that isn't saved
+        let (player_account, account_saved) = match wallet::load_or_create() {
+            Ok(account) => (account, true),
+            Err(error) => {
+                println!("{:#}", error);
+                (LocalAccount::generate(&mut OsRng), false)
+            }
+        };
         let transaction_factory = TransactionFactory::new(ChainId::new(149));
         let contract_account: AccountAddress = AccountAddress::from_hex_literal("0x35bcaf14a08f75b726ff25dbad2063286a4b3ff191753b0f1d57913b2038a687").unwrap();
         let module_id = ModuleId::new(
@@ -79,11 +242,12 @@ impl GDK {
             rest_client,
             faucet_client,
             // coin_client,
-            player_account,
+            player_account: Arc::new(player_account),
+            account_saved,
             transaction_factory,
             game_address: None,
             module_id,
-            state: State::None
+            runtime: Handle::current()
         };
 
 
@@ -110,115 +274,325 @@ impl GDK {
     
     }
 
-    async fn fund(&self) {
-        let result = self.faucet_client
-            .fund(self.player_account.address(), 100_000_000)
-            .await
-            .context("Failed to fund player's account");
+    // APT balance of the player, in octas
+    pub async fn get_balance(&self) -> Result<u64> {
+        balance_of(&self.rest_client, self.player_account.address()).await
+    }
+
+    // Tops the account up from the faucet when it can't pay for a game, returning the balance.
+    // It doesn't borrow the GDK, so it can run as a task, and gives up on a node that doesn't answer
+    pub fn ensure_funded(&self) -> impl Future<Output = Result<u64>> + Send + 'static {
+        let rest_client = self.rest_client.clone();
+        let faucet_client = self.faucet_client.clone();
+        let address = self.player_account.address();
+        self.on_runtime(async move {
+            tokio::time::timeout(FUNDING_TIMEOUT, fund_if_needed(&rest_client, &faucet_client, address))
+                .await
+                .with_context(|| format!("{} didn't answer within {} seconds", NODE_URL.as_str(), FUNDING_TIMEOUT.as_secs()))?
+        })
+    }
+
+    // Runs `future` on the Tokio runtime the REST client needs, the returned future can be polled from Bevy's task pools
+    fn on_runtime<T: Send + 'static>(&self, future: impl Future<Output = Result<T>> + Send + 'static) -> impl Future<Output = Result<T>> + Send + 'static {
+        let task = self.runtime.spawn(future);
+        async move {
+            task.await.context("A background task panicked")?
+        }
+    }
+
+    pub fn player_address(&self) -> AccountAddress {
+        self.player_account.address()
     }
 
     pub fn get_address(&self)->String {
         return self.player_account.address().to_standard_string();
     }
 
+    pub fn is_account_saved(&self) -> bool {
+        self.account_saved
+    }
+
+    pub fn get_private_key(&self)->String {
+        wallet::private_key_hex(&self.player_account)
+    }
+
+    // Switches to the account of a pasted private key or mnemonic, which is saved for the next launches
+    pub fn import_key(&mut self, text: &str) -> Result<()> {
+        self.player_account = Arc::new(wallet::import(text)?);
+        self.account_saved = true;
+        Ok(())
+    }
+
     pub async fn get_latest_transaction_version(&self){
         let info = self.rest_client.get_ledger_information().await;
         info.unwrap().into_inner().version;        
     }
 
-    pub async fn create_game(&mut self){      
-        self.state = State::Creating;
-          
-        println!("{}",self.player_account.address());
+    // Hosts a game under the player's account, resolving to its address once the transaction is executed.
+    // It doesn't borrow the GDK, so a Bevy system can wait for it as a task
+    pub fn create_game(&self) -> impl Future<Output = Result<AccountAddress>> + Send + 'static {
+        let submitter = self.submitter();
+        let game_address = self.player_address();
+        self.on_runtime(async move {
+            submitter.submit("create_game", vec![], DEFAULT_MAX_GAS).await?;
+            Ok(game_address)
+        })
+    }
 
-        
+    // Joins the game hosted at `game_address` as the guest, resolving to that address once the transaction is executed
+    pub fn join_game(&self, game_address: AccountAddress) -> impl Future<Output = Result<AccountAddress>> + Send + 'static {
+        let submitter = self.submitter();
+        self.on_runtime(async move {
+            submitter.submit("join_game", vec![bcs::to_bytes(&game_address)?], DEFAULT_MAX_GAS).await?;
+            Ok(game_address)
+        })
+    }
 
-        // let chain_id = self.rest_client.get_index()
-        //     .await
-        //     .context("Failed to get chain ID")?
-        //     .inner()
-        //     .chain_id;
-        // println!("chain id: {}",chain_id);
-
-        let function_id:Identifier = ident_str!("create_game").to_owned();
-        let type_tags: Vec<TypeTag> = vec![];
-        let args : Vec<Vec<u8>> = vec![];
-        let entry_function = EntryFunction::new(self.module_id.clone(),function_id,type_tags,args);
-        let builder = self.transaction_factory
-            .entry_function(entry_function)
-            .sender(self.player_account.address())
-            .sequence_number(self.player_account.sequence_number())
-            .max_gas_amount(5_000)
-            .gas_unit_price(100)
-            .expiration_timestamp_secs(SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs()
-                + 10 // timeout_seconds
-            );
-        let raw_transaction = builder.build();
-        let signed_transaction = self.player_account.sign_transaction(raw_transaction);
-        // let simulated_result = self.rest_client.simulate_bcs(&signed_transaction).await;
-        // match simulated_result {
-        //     Err(error) => println!("Failed in simulation {}",error),
-        //     _ => println!("{}","Simulation succeeded.")
-        // }
-        
-        let result: std::result::Result<aptos_sdk::rest_client::Response<Transaction>, aptos_sdk::rest_client::error::RestError> = self.rest_client.submit_and_wait(&signed_transaction)
-            .await;        
-
-        self.game_address = Some(self.player_account.address());
-                
-        match result {
-            Err(error) => println!("{}",error),
-            Ok(response) => println!("{}","Game started.")
-        };
+    // Rolls for the active player, returning the dice to play
+    pub async fn roll_the_dice(&mut self) -> Result<Vec<u8>> {
+        self.send(ChainAction::Roll).await?;
+        self.get_dices().await
+    }
 
-        self.state = State::Created;
-    }
-
-    async fn roll_the_dice(&self){
-        let function_id:Identifier = ident_str!("roll_the_dice").to_owned();
-        let type_tags: Vec<TypeTag> = vec![];
-        let args : Vec<Vec<u8>> = vec![];
-        let entry_function = EntryFunction::new(self.module_id.clone(),function_id,type_tags,args);
-        let builder = self.transaction_factory.entry_function(entry_function);
-        let raw_transaction = builder.build();
-        let signed_transaction = self.player_account.sign_transaction(raw_transaction);
-        let result = self.rest_client.submit_and_wait(&signed_transaction)
-            .await;            
-        match result {
-            Err(error) => println!("{}",error),
-            Ok(response) => {
-                println!("{}","Dice rolled.");                
-            }
+    // Moves a nut from the global tower, or the bar, by the die at `dice_index` of the dice left, true when it was the turn's last die
+    pub async fn choose_move(&mut self, tower_index: u8, dice_index: u8) -> Result<bool> {
+        let transaction = self.send(ChainAction::Move{ tower_index, dice_index }).await?;
+        Ok(emitted(&transaction, "EndOfTurnEvent"))
+    }
+
+    // Bears a nut off from the tower, numbered from the active player's ace point, with the die at `dice_index`
+    pub async fn bear_off(&mut self, player_tower_index: u8, dice_index: u8) -> Result<()> {
+        self.send(ChainAction::BearOff{ player_tower_index, dice_index }).await?;
+        Ok(())
+    }
+
+    // Passes the turn when none of the dice left can be played
+    pub async fn pass_turn(&mut self) -> Result<()> {
+        self.send(ChainAction::Pass).await?;
+        Ok(())
+    }
+
+    pub async fn forfeit(&mut self) -> Result<()> {
+        self.send(ChainAction::Forfeit).await?;
+        Ok(())
+    }
+
+    // Sends `action` and reads the game it leaves, the two one after the other on the runtime
+    pub fn play(&self, action: ChainAction) -> impl Future<Output = Result<OnChainGame>> + Send + 'static {
+        let submitter = self.submitter();
+        let rest_client = self.rest_client.clone();
+        let resource = self.game_resource();
+        let game_address = self.current_game();
+        self.on_runtime(async move {
+            let game_address = game_address?;
+            let (function, args, max_gas_amount) = entry_call(action, game_address)?;
+            submitter.submit(function, args, max_gas_amount).await?;
+            read_game(&rest_client, &resource, game_address).await
+        })
+    }
+
+    // Sends `action` for the current game without borrowing the GDK, so a Bevy system can wait for it as a task
+    pub fn send(&self, action: ChainAction) -> impl Future<Output = Result<Transaction>> + Send + 'static {
+        let submitter = self.submitter();
+        let game_address = self.current_game();
+        self.on_runtime(async move {
+            let (function, args, max_gas_amount) = entry_call(action, game_address?)?;
+            submitter.submit(function, args, max_gas_amount).await
+        })
+    }
+
+    // Removes the game hosted by the player from their account, so they can host a new one
+    pub async fn cleanup(&mut self) -> Result<()> {
+        self.submit("cleanup", vec![], DEFAULT_MAX_GAS).await?;
+        if self.game_address == Some(self.player_account.address()) {
+            self.game_address = None;
         }
+        Ok(())
     }
 
-    pub async fn join_game(&mut self,game_addr_encoded: String ){
-        let game_addr = AccountAddress::from_hex_literal(&game_addr_encoded.as_str()).unwrap();
-        self.state = State::Joining;
-        let function_id:Identifier = ident_str!("join_game").to_owned();
-        let type_tags: Vec<TypeTag> = vec![TypeTag::Address];
-        let args : Vec<Vec<u8>> = vec![
-            bcs::to_bytes(&game_addr).unwrap()
-        ];
-        let entry_function = EntryFunction::new(self.module_id.clone(),function_id,type_tags,args);
-        let builder = self.transaction_factory.entry_function(entry_function);
-        let raw_transaction = builder.build();
-        let signed_transaction = self.player_account.sign_transaction(raw_transaction);
-        let result = self.rest_client.submit_and_wait(&signed_transaction)
-            .await;            
-        match result {
-            Err(error) => println!("{}",error),
-            Ok(response) => {
-                println!("{}","Joined game.");
-            }
+    // Dice the active player still has to play, as pips from 1 to 6
+    pub async fn get_dices(&self) -> Result<Vec<u8>> {
+        let request = ViewFunction{
+            module: self.module_id.clone(),
+            function: Identifier::new("get_dices")?,
+            ty_args: vec![],
+            args: vec![bcs::to_bytes(&self.current_game()?)?]
         };
-        self.game_address = Some(game_addr);
-        self.state = State::Started;
+        // Every returned value comes back BCS encoded on its own
+        let values: Vec<Vec<u8>> = self.rest_client
+            .view_bcs(&request, None)
+            .await
+            .context("Failed to get the dice")?
+            .into_inner();
+        let dices: Vec<u8> = bcs::from_bytes(values.first().context("get_dices returned nothing")?)?;
+        // The contract draws each die from 0 to 5
+        Ok(dices.into_iter().map(|die| die + 1).collect())
+    }
+
+    // The state of the game at `game_addr` as the contract holds it
+    pub async fn get_game(&self, game_addr: AccountAddress) -> Result<OnChainGame> {
+        read_game(&self.rest_client, &self.game_resource(), game_addr).await
+    }
+
+    // Reads the current game without borrowing the GDK, for tasks that outlive the system starting them
+    pub fn fetch_game(&self) -> impl Future<Output = Result<OnChainGame>> + Send + 'static {
+        let rest_client = self.rest_client.clone();
+        let resource = self.game_resource();
+        let game_address = self.current_game();
+        self.on_runtime(async move {
+            read_game(&rest_client, &resource, game_address?).await
+        })
+    }
+
+    // Polls the players of the current game for its events in the background. The watcher stops on a
+    // forfeit, otherwise it runs until its task is aborted
+    pub fn watch_events(&self) -> Result<(Receiver<GameEvent>, JoinHandle<()>)> {
+        let game_address = self.current_game()?;
+        let rest_client = self.rest_client.clone();
+        let resource = self.game_resource();
+        let (sender, receiver) = mpsc::channel();
+        let task = self.runtime.spawn(watch_game(rest_client, resource, game_address, sender));
+        Ok((receiver, task))
+    }
+
+    fn game_resource(&self) -> String {
+        format!("{}::{}::Game", self.module_id.address().to_hex_literal(), self.module_id.name())
+    }
+
+    fn current_game(&self) -> Result<AccountAddress> {
+        self.game_address.context("No game was created or joined")
+    }
+
+    // Signs and submits a call to the contract's entry function `function`, waiting until it's executed
+    async fn submit(&self, function: &'static str, args: Vec<Vec<u8>>, max_gas_amount: u64) -> Result<Transaction> {
+        self.on_runtime(self.submitter().submit(function, args, max_gas_amount)).await
+    }
+
+    fn submitter(&self) -> Submitter {
+        Submitter{
+            rest_client: self.rest_client.clone(),
+            transaction_factory: self.transaction_factory.clone(),
+            module_id: self.module_id.clone(),
+            account: self.player_account.clone()
+        }
     }
     
 }
 
+async fn balance_of(rest_client: &Client, address: AccountAddress) -> Result<u64> {
+    CoinClient::new(rest_client)
+        .get_account_balance(&address)
+        .await
+        .context("Failed to get the player's balance")
+}
+
+async fn fund_if_needed(rest_client: &Client, faucet_client: &FaucetClient, address: AccountAddress) -> Result<u64> {
+    let balance = match balance_of(rest_client, address).await {
+        Ok(balance) => balance,
+        // A new account only exists on chain once it has been funded
+        Err(error) if is_not_found(&error) => 0,
+        Err(error) => return Err(error)
+    };
+    if balance >= MIN_BALANCE {
+        return Ok(balance);
+    }
+    if !has_faucet() {
+        bail!("The account has {} octas, at least {} are needed to pay for gas and {} has no faucet", balance, MIN_BALANCE, NODE_URL.as_str());
+    }
+    faucet_client
+        .fund(address, FUNDING_AMOUNT)
+        .await
+        .context("Failed to fund player's account")?;
+    balance_of(rest_client, address).await
+}
+
+// Whether the node answered that the account, or its coin store, doesn't exist
+fn is_not_found(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| match cause.downcast_ref::<RestError>() {
+        Some(RestError::Api(response)) => matches!(response.error.error_code, AptosErrorCode::AccountNotFound | AptosErrorCode::ResourceNotFound),
+        _ => false
+    })
+}
+
+async fn read_game(rest_client: &Client, resource: &str, game_addr: AccountAddress) -> Result<OnChainGame> {
+    Ok(rest_client
+        .get_account_resource_bcs::<OnChainGame>(game_addr, resource)
+        .await
+        .with_context(|| format!("Failed to read the game at {}", game_addr))?
+        .into_inner())
+}
+
+// Events of the game at `game_address` are only found in the transactions emitting them, so the watcher
+// scans the new transactions of both players, picking the guest up once they joined
+async fn watch_game(rest_client: Client, resource: String, game_address: AccountAddress, events: Sender<GameEvent>) {
+    // Each player's next sequence number to scan, what they sent before the watcher started is skipped
+    let mut players: Vec<(AccountAddress, u64)> = vec![];
+    loop {
+        match poll_game(&rest_client, &resource, game_address, &mut players, &events).await {
+            Ok(true) => return,
+            Ok(false) => (),
+            Err(error) => println!("{:#}", error)
+        }
+        tokio::time::sleep(EVENT_POLL_INTERVAL).await;
+    }
+}
 
+// Forwards the events found since the last poll, true once the watcher is done
+async fn poll_game(rest_client: &Client, resource: &str, game_address: AccountAddress, players: &mut Vec<(AccountAddress, u64)>, events: &Sender<GameEvent>) -> Result<bool> {
+    if players.len() < 2 {
+        let game = read_game(rest_client, resource, game_address).await?;
+        for player in [game.host_player, game.guest_player].into_iter().flatten() {
+            if players.iter().all(|(address, _)| *address != player.owner) {
+                let account = rest_client.get_account(player.owner).await.context("Failed to get a player's account")?.into_inner();
+                players.push((player.owner, account.sequence_number));
+            }
+        }
+    }
+    for (address, next) in players.iter_mut() {
+        let transactions = rest_client
+            .get_account_transactions(*address, Some(*next), Some(TRANSACTIONS_PER_POLL))
+            .await
+            .context("Failed to get a player's transactions")?
+            .into_inner();
+        for transaction in transactions.iter() {
+            *next += 1;
+            for event in game_events(transaction, game_address) {
+                let game_over = matches!(event, GameEvent::GameOver{..});
+                if events.send(event).is_err() || game_over {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn game_events(transaction: &Transaction, game_address: AccountAddress) -> Vec<GameEvent> {
+    let Transaction::UserTransaction(transaction) = transaction else {
+        return vec![];
+    };
+    let player = *transaction.request.sender.inner();
+    transaction.events.iter().filter(|event| {
+        event.data.get("game_address").and_then(|address| address.as_str()).and_then(|address| AccountAddress::from_str(address).ok()) == Some(game_address)
+    }).filter_map(|event| {
+        if is_contract_event(event, "EndOfTurnEvent") {
+            Some(GameEvent::EndOfTurn{ player })
+        } else if is_contract_event(event, "GameOverEvent") {
+            Some(GameEvent::GameOver{ player })
+        } else {
+            None
+        }
+    }).collect()
+}
+
+// Whether the contract emitted its event `name` during the transaction
+fn emitted(transaction: &Transaction, name: &str) -> bool {
+    let Transaction::UserTransaction(transaction) = transaction else {
+        return false;
+    };
+    transaction.events.iter().any(|event| is_contract_event(event, name))
+}
+
+fn is_contract_event(event: &Event, name: &str) -> bool {
+    event.typ.to_string().ends_with(&format!("::backgammon::{}", name))
+}
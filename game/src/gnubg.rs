@@ -0,0 +1,448 @@
+use std::fmt;
+
+use crate::cube::Cube;
+use crate::match_play::Match;
+use crate::model::GameModel;
+use crate::utils::{opponent, player_to_global_tower_index, roll_to_dice, Board, GameResult, ResultType, Tower, NUTS_PER_PLAYER, PLAYER_GUEST, PLAYER_HOST, TOWERS_COUNT};
+
+// GNU Backgammon Position ID and Match ID
+// https://www.gnu.org/software/gnubg/manual/html_node/A-technical-description-of-the-Position-ID.html
+// https://www.gnu.org/software/gnubg/manual/html_node/A-technical-description-of-the-Match-ID.html
+
+const POSITION_ID_BYTES: usize = 10;
+const MATCH_ID_BYTES: usize = 9;
+// Slots per player in a Position ID, the 24 towers followed by the bar
+const POSITION_SLOTS: usize = TOWERS_COUNT + 1;
+const CUBE_CENTERED: u32 = 3;
+const GAME_PLAYING: u32 = 1;
+const GAME_OVER: u32 = 2;
+const GAME_DROPPED: u32 = 4;
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IdError {
+    InvalidLength,
+    InvalidCharacter(char),
+    TooManyNuts(usize),
+    InvalidField(&'static str)
+}
+
+impl fmt::Display for IdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IdError::InvalidLength => write!(f, "the id has the wrong length"),
+            IdError::InvalidCharacter(character) => write!(f, "'{}' isn't a base64 character", character),
+            IdError::TooManyNuts(player) => write!(f, "player {} has more than {} nuts", player, NUTS_PER_PLAYER),
+            IdError::InvalidField(field) => write!(f, "the {} field is out of range", field)
+        }
+    }
+}
+
+impl std::error::Error for IdError {}
+
+// gnubg numbers a player's points from their own ace point, we number towers in their direction of travel
+fn slot_to_global_tower_index(player: usize, slot: usize) -> usize {
+    player_to_global_tower_index(player, TOWERS_COUNT - 1 - slot)
+}
+
+fn slot_nuts(board: &Board, player: usize, slot: usize) -> u8 {
+    if slot == TOWERS_COUNT {
+        board.bar.nuts(player)
+    }else{
+        board.nuts_of(player, slot_to_global_tower_index(player, slot))
+    }
+}
+
+// The Position ID of `board` as seen by `player_on_roll`
+pub fn position_id(board: &Board, player_on_roll: usize) -> String {
+    let mut bits = BitWriter::new(POSITION_ID_BYTES);
+    for player in [player_on_roll, opponent(player_on_roll)] {
+        for slot in 0..POSITION_SLOTS {
+            for _ in 0..slot_nuts(board, player, slot) {
+                bits.write(1, 1);
+            }
+            bits.write(0, 1);
+        }
+    }
+    encode_base64(&bits.bytes)
+}
+
+pub fn board_from_position_id(id: &str, player_on_roll: usize) -> Result<Board, IdError> {
+    let bytes = decode_base64(id, POSITION_ID_BYTES)?;
+    let mut bits = BitReader::new(&bytes);
+
+    let mut board = Board::empty();
+    for player in [player_on_roll, opponent(player_on_roll)] {
+        let mut total: u8 = 0;
+        for slot in 0..POSITION_SLOTS {
+            let mut nuts: u8 = 0;
+            while bits.read(1).ok_or(IdError::InvalidLength)? == 1 {
+                nuts += 1;
+                total += 1;
+                if total > NUTS_PER_PLAYER {
+                    return Err(IdError::TooManyNuts(player));
+                }
+            }
+            if nuts == 0 {
+                continue;
+            }
+            if slot == TOWERS_COUNT {
+                *board.bar.nuts_mut(player) = nuts;
+            }else{
+                let global_tower_index = slot_to_global_tower_index(player, slot);
+                if board.towers[global_tower_index].nuts > 0 {
+                    return Err(IdError::InvalidField("tower"));
+                }
+                board.towers[global_tower_index] = Tower{
+                    nuts,
+                    owner: player as u8
+                };
+            }
+        }
+        *board.borne_off.nuts_mut(player) = NUTS_PER_PLAYER - total;
+    }
+    board.rehash();
+    Ok(board)
+}
+
+// Everything a Match ID carries besides the board
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MatchId {
+    pub cube_value: u32,
+    pub cube_owner: Option<usize>,
+    // Player holding the dice, the one the Position ID is seen from
+    pub player_on_roll: usize,
+    pub is_crawford: bool,
+    // 0 no game, 1 playing, 2 over, 3 resigned, 4 ended by a dropped double
+    pub game_state: u32,
+    // Player who has to act next, the opponent of the player on roll while a double is offered
+    pub turn: usize,
+    pub double_offered: bool,
+    pub resignation: u32,
+    // Both zero before rolling
+    pub dice: [u8;2],
+    // Zero for money games
+    pub match_length: u32,
+    pub score: [u32;2]
+}
+
+impl MatchId {
+    pub fn from_game(model: &GameModel, current_match: Option<&Match>) -> MatchId {
+        let double_offered = model.cube.offered_by.is_some();
+        MatchId{
+            cube_value: model.cube.value,
+            cube_owner: model.cube.owner,
+            player_on_roll: model.active_player,
+            is_crawford: current_match.is_some_and(|current_match| current_match.is_crawford),
+            game_state: game_state(model),
+            turn: if double_offered { opponent(model.active_player) } else { model.active_player },
+            double_offered,
+            resignation: 0,
+            dice: model.roll.unwrap_or([0, 0]),
+            match_length: current_match.map_or(0, |current_match| current_match.length),
+            score: current_match.map_or([0, 0], |current_match| current_match.score)
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let mut bits = BitWriter::new(MATCH_ID_BYTES);
+        bits.write(self.cube_value.trailing_zeros(), 4);
+        bits.write(self.cube_owner.map_or(CUBE_CENTERED, |owner| owner as u32), 2);
+        bits.write(self.player_on_roll as u32, 1);
+        bits.write(self.is_crawford as u32, 1);
+        bits.write(self.game_state, 3);
+        bits.write(self.turn as u32, 1);
+        bits.write(self.double_offered as u32, 1);
+        bits.write(self.resignation, 2);
+        bits.write(self.dice[0] as u32, 3);
+        bits.write(self.dice[1] as u32, 3);
+        bits.write(self.match_length, 15);
+        bits.write(self.score[PLAYER_HOST], 15);
+        bits.write(self.score[PLAYER_GUEST], 15);
+        encode_base64(&bits.bytes)
+    }
+
+    pub fn decode(id: &str) -> Result<MatchId, IdError> {
+        let bytes = decode_base64(id, MATCH_ID_BYTES)?;
+        let mut bits = BitReader::new(&bytes);
+        let mut read = |count: usize| bits.read(count).ok_or(IdError::InvalidLength);
+
+        let cube_log = read(4)?;
+        let cube_owner = match read(2)? {
+            CUBE_CENTERED => None,
+            2 => return Err(IdError::InvalidField("cube owner")),
+            owner => Some(owner as usize)
+        };
+        let player_on_roll = read(1)? as usize;
+        let is_crawford = read(1)? == 1;
+        let game_state = read(3)?;
+        let turn = read(1)? as usize;
+        let double_offered = read(1)? == 1;
+        let resignation = read(2)?;
+        let dice = [read(3)? as u8, read(3)? as u8];
+        let match_length = read(15)?;
+        let score = [read(15)?, read(15)?];
+
+        if cube_log > 6 {
+            return Err(IdError::InvalidField("cube value"));
+        }
+        if game_state > 4 {
+            return Err(IdError::InvalidField("game state"));
+        }
+        if dice.iter().any(|die| *die > 6) || (dice[0] == 0) != (dice[1] == 0) {
+            return Err(IdError::InvalidField("dice"));
+        }
+
+        Ok(MatchId{
+            cube_value: 1 << cube_log,
+            cube_owner,
+            player_on_roll,
+            is_crawford,
+            game_state,
+            turn,
+            double_offered,
+            resignation,
+            dice,
+            match_length,
+            score
+        })
+    }
+
+    // A game model for `board` in the state this id describes
+    pub fn to_game_model(&self, board: Board) -> GameModel {
+        let mut model = GameModel::new();
+        model.board = board;
+        model.active_player = self.player_on_roll;
+        if self.dice[0] > 0 {
+            model.roll = Some(self.dice);
+            model.dice = roll_to_dice(self.dice);
+        }
+        model.cube = Cube{
+            value: self.cube_value,
+            owner: self.cube_owner,
+            offered_by: if self.double_offered { Some(self.player_on_roll) } else { None },
+            enabled: !self.is_crawford
+        };
+        model.result = match self.game_state {
+            // The doubler keeps the dice when their double is dropped
+            GAME_DROPPED => Some(GameResult{
+                winner: self.player_on_roll,
+                result_type: ResultType::Single
+            }),
+            _ => model.board.result()
+        };
+        model
+    }
+
+    pub fn to_match(&self) -> Option<Match> {
+        if self.match_length == 0 {
+            return None;
+        }
+        let mut current_match = Match::new(self.match_length);
+        current_match.score = self.score;
+        current_match.is_crawford = self.is_crawford;
+        // Without the Crawford flag a player at match point means the Crawford game was played
+        current_match.is_post_crawford = !self.is_crawford
+            && [PLAYER_HOST, PLAYER_GUEST].into_iter().any(|player| current_match.away(player) == 1);
+        Some(current_match)
+    }
+}
+
+// Games don't end by resignation here, so a game over with nuts left on both sides was a dropped double
+fn game_state(model: &GameModel) -> u32 {
+    if !model.is_over() {
+        GAME_PLAYING
+    } else if model.board.result().is_none() {
+        GAME_DROPPED
+    } else {
+        GAME_OVER
+    }
+}
+
+// Both ids written the way gnubg shows them together, "PositionID:MatchID"
+pub fn game_id(model: &GameModel, current_match: Option<&Match>) -> String {
+    format!("{}:{}", position_id(&model.board, model.active_player), MatchId::from_game(model, current_match).encode())
+}
+
+pub fn game_from_id(id: &str) -> Result<(GameModel, Option<Match>), IdError> {
+    let (position, match_id) = id.trim().split_once(':').ok_or(IdError::InvalidLength)?;
+    let match_id = MatchId::decode(match_id)?;
+    let board = board_from_position_id(position, match_id.player_on_roll)?;
+    Ok((match_id.to_game_model(board), match_id.to_match()))
+}
+
+// Bits are packed least significant first, both within a byte and within a field
+struct BitWriter {
+    bytes: Vec<u8>,
+    position: usize
+}
+
+impl BitWriter {
+    fn new(size: usize) -> BitWriter {
+        BitWriter{
+            bytes: vec![0;size],
+            position: 0
+        }
+    }
+
+    fn write(&mut self, value: u32, count: usize) {
+        for bit in 0..count {
+            if (value >> bit) & 1 == 1 {
+                self.bytes[self.position / 8] |= 1 << (self.position % 8);
+            }
+            self.position += 1;
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize
+}
+
+impl<'a> BitReader<'a> {
+    fn new(bytes: &'a [u8]) -> BitReader<'a> {
+        BitReader{
+            bytes,
+            position: 0
+        }
+    }
+
+    fn read(&mut self, count: usize) -> Option<u32> {
+        let mut value: u32 = 0;
+        for bit in 0..count {
+            let byte = self.bytes.get(self.position / 8)?;
+            value |= (((byte >> (self.position % 8)) & 1) as u32) << bit;
+            self.position += 1;
+        }
+        Some(value)
+    }
+}
+
+// Standard base64 without the trailing padding, as gnubg prints it
+fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, byte)| group | (*byte as u32) << (16 - 8 * index));
+        for index in 0..chunk.len() + 1 {
+            text.push(BASE64_ALPHABET[((group >> (18 - 6 * index)) & 0x3f) as usize] as char);
+        }
+    }
+    text
+}
+
+fn decode_base64(text: &str, size: usize) -> Result<Vec<u8>, IdError> {
+    let text = text.trim().trim_end_matches('=');
+    if text.len() != (size * 8).div_ceil(6) {
+        return Err(IdError::InvalidLength);
+    }
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(size);
+    let mut group: u32 = 0;
+    let mut group_bits = 0;
+    for character in text.chars() {
+        let value = BASE64_ALPHABET.iter().position(|c| *c as char == character).ok_or(IdError::InvalidCharacter(character))?;
+        group = (group << 6) | value as u32;
+        group_bits += 6;
+        if group_bits >= 8 {
+            group_bits -= 8;
+            bytes.push((group >> group_bits) as u8);
+            group &= (1 << group_bits) - 1;
+        }
+    }
+    bytes.truncate(size);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::model::random_roll;
+    use crate::utils::initialize;
+
+    fn assert_round_trip(model: &GameModel, current_match: Option<&Match>) {
+        let id = game_id(model, current_match);
+        let (decoded, decoded_match) = game_from_id(&id).unwrap();
+        assert_eq!(decoded.board, model.board, "{}", id);
+        assert_eq!(decoded.active_player, model.active_player, "{}", id);
+        assert_eq!(decoded.roll, model.roll, "{}", id);
+        assert_eq!(decoded.dice, model.dice, "{}", id);
+        assert_eq!(decoded.cube, model.cube, "{}", id);
+        assert_eq!(decoded.result, model.result, "{}", id);
+        assert_eq!(MatchId::from_game(&decoded, decoded_match.as_ref()), MatchId::from_game(model, current_match), "{}", id);
+        assert_eq!(game_id(&decoded, decoded_match.as_ref()), id);
+    }
+
+    #[test]
+    fn start_position_id() {
+        assert_eq!(position_id(&initialize(), PLAYER_HOST), "4HPwATDgc/ABMA");
+        assert_eq!(position_id(&initialize(), PLAYER_GUEST), "4HPwATDgc/ABMA");
+    }
+
+    #[test]
+    fn position_id_round_trips() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            let mut model = GameModel::new();
+            while !model.is_over() {
+                for player in [PLAYER_HOST, PLAYER_GUEST] {
+                    let board = board_from_position_id(&position_id(&model.board, player), player).unwrap();
+                    assert_eq!(board, model.board);
+                }
+                model.roll_dice(random_roll(&mut rng)).unwrap();
+                let turns = model.board.legal_turns(model.active_player, &model.dice);
+                let turn = turns[rng.gen_range(0..turns.len())].clone();
+                model.play_turn(&turn).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn match_id_round_trips() {
+        let mut model = GameModel::new();
+        assert_round_trip(&model, None);
+        model.roll_dice([6, 4]).unwrap();
+        assert_round_trip(&model, None);
+
+        let mut current_match = Match::new(7);
+        current_match.score = [3, 5];
+        let mut model = current_match.new_game();
+        model.offer_double().unwrap();
+        assert_round_trip(&model, Some(&current_match));
+        model.take_double().unwrap();
+        model.roll_dice([3, 3]).unwrap();
+        assert_round_trip(&model, Some(&current_match));
+    }
+
+    #[test]
+    fn crawford_game_round_trips() {
+        let mut current_match = Match::new(5);
+        current_match.score = [4, 2];
+        current_match.is_crawford = true;
+        let mut model = current_match.new_game();
+        model.active_player = PLAYER_GUEST;
+        model.roll_dice([2, 1]).unwrap();
+        assert_round_trip(&model, Some(&current_match));
+
+        let (decoded, decoded_match) = game_from_id(&game_id(&model, Some(&current_match))).unwrap();
+        assert!(!decoded.cube.enabled);
+        assert!(decoded_match.unwrap().is_crawford);
+    }
+
+    #[test]
+    fn dropped_double_round_trips() {
+        let mut current_match = Match::new(5);
+        let mut model = current_match.new_game();
+        model.offer_double().unwrap();
+        model.drop_double().unwrap();
+        assert_eq!(MatchId::from_game(&model, Some(&current_match)).game_state, GAME_DROPPED);
+        assert_round_trip(&model, Some(&current_match));
+
+        current_match.record_game(&model);
+        assert_eq!(current_match.score, [1, 0]);
+    }
+}
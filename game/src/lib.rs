@@ -0,0 +1,16 @@
+// Rules, engine and analysis, everything that runs without Bevy or the chain.
+// Shared by the game and the headless tools under src/bin
+pub mod utils;
+pub mod zobrist;
+pub mod cube;
+pub mod model;
+pub mod match_play;
+pub mod gnubg;
+pub mod bot;
+pub mod search;
+pub mod nn;
+pub mod rollout;
+pub mod analysis;
+pub mod bearoff;
+pub mod engine;
+pub mod chain;
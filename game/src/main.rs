@@ -1,12 +1,16 @@
 // https://www.bkgm.com/rules.html
-mod utils;
 mod gdk;
+mod wallet;
 mod drawing;
 mod ui;
 mod resources;
 mod states;
 mod components;
+mod events;
 mod game;
+mod controls;
+
+use backgammon::{analysis, bot, chain, cube, gnubg, match_play, model, nn, search, utils};
 
 use game::run_game;
 
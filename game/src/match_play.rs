@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+
+use crate::model::GameModel;
+use crate::utils::{GameResult, PLAYER_GUEST, PLAYER_HOST};
+
+pub const DEFAULT_MATCH_LENGTH: u32 = 5;
+pub const MATCH_LENGTHS: [u32;4] = [1, 3, 5, 7];
+
+// A match is a series of games until one player collects `length` points
+// https://www.bkgm.com/rules.html#crawford
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Match {
+    pub length: u32,
+    // Points collected by each player, indexed by PLAYER_HOST and PLAYER_GUEST
+    pub score: [u32;2],
+    // The game being played is the Crawford game, the first one after a player reached match point
+    pub is_crawford: bool,
+    // The Crawford game is over and the cube is back in play for the rest of the match
+    pub is_post_crawford: bool,
+    // Every finished game with the points it was worth, in order
+    pub games: Vec<(GameResult, u32)>
+}
+
+impl Match {
+    pub fn new(length: u32) -> Match {
+        Match{
+            length,
+            score: [0, 0],
+            is_crawford: false,
+            is_post_crawford: false,
+            games: vec![]
+        }
+    }
+
+    // Points `player` still needs to win the match
+    pub fn away(&self, player: usize) -> u32 {
+        self.length.saturating_sub(self.score[player])
+    }
+
+    pub fn winner(&self) -> Option<usize> {
+        [PLAYER_HOST, PLAYER_GUEST].into_iter().find(|player| self.away(*player) == 0)
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.winner().is_some()
+    }
+
+    // The next game of the match, without a cube if it's the Crawford game
+    pub fn new_game(&self) -> GameModel {
+        let mut model = GameModel::new();
+        model.cube.enabled = !self.is_crawford;
+        model
+    }
+
+    pub fn record_game(&mut self, model: &GameModel) {
+        let (Some(result), Some(points)) = (model.result, model.points()) else {
+            return;
+        };
+        self.score[result.winner] += points;
+        self.games.push((result, points));
+
+        if self.is_crawford {
+            self.is_crawford = false;
+            self.is_post_crawford = true;
+        }else if !self.is_post_crawford && !self.is_over() {
+            self.is_crawford = [PLAYER_HOST, PLAYER_GUEST].into_iter().any(|player| self.away(player) == 1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::GameError;
+    use crate::utils::ResultType;
+
+    // A finished game won by `winner` with a single game result at the cube's `value`
+    fn finished_game(current_match: &Match, winner: usize, value: u32) -> GameModel {
+        let mut model = current_match.new_game();
+        model.cube.value = value;
+        model.result = Some(GameResult{
+            winner,
+            result_type: ResultType::Single
+        });
+        model
+    }
+
+    #[test]
+    fn crawford_rule_holds_for_exactly_one_game() {
+        let mut current_match = Match::new(3);
+        current_match.record_game(&finished_game(&current_match, PLAYER_HOST, 2));
+        assert_eq!(current_match.away(PLAYER_HOST), 1);
+        assert!(current_match.is_crawford);
+
+        // No doubling in the Crawford game
+        let mut crawford_game = current_match.new_game();
+        assert!(!crawford_game.cube.enabled);
+        assert!(matches!(crawford_game.offer_double(), Err(GameError::CannotDouble)));
+
+        current_match.record_game(&finished_game(&current_match, PLAYER_GUEST, 1));
+        assert!(!current_match.is_crawford);
+        assert!(current_match.is_post_crawford);
+
+        // Nor is there a second Crawford game when the other player reaches match point too
+        current_match.record_game(&finished_game(&current_match, PLAYER_GUEST, 1));
+        assert_eq!(current_match.away(PLAYER_GUEST), 1);
+        assert!(!current_match.is_crawford);
+        let mut next_game = current_match.new_game();
+        assert!(next_game.cube.enabled);
+        assert!(next_game.offer_double().is_ok());
+
+        current_match.record_game(&finished_game(&current_match, PLAYER_GUEST, 1));
+        assert_eq!(current_match.winner(), Some(PLAYER_GUEST));
+        assert_eq!(current_match.games.len(), 4);
+    }
+
+    #[test]
+    fn matches_round_trip_through_serde() {
+        let mut current_match = Match::new(5);
+        current_match.record_game(&finished_game(&current_match, PLAYER_HOST, 4));
+        current_match.record_game(&finished_game(&current_match, PLAYER_GUEST, 2));
+        assert!(current_match.is_post_crawford);
+
+        let bytes = bcs::to_bytes(&current_match).unwrap();
+        assert_eq!(bcs::from_bytes::<Match>(&bytes).unwrap(), current_match);
+        let json = serde_json::to_string(&current_match).unwrap();
+        assert_eq!(serde_json::from_str::<Match>(&json).unwrap(), current_match);
+    }
+
+    #[test]
+    fn winning_the_match_outright_skips_the_crawford_game() {
+        let mut current_match = Match::new(3);
+        current_match.record_game(&finished_game(&current_match, PLAYER_HOST, 4));
+        assert!(current_match.is_over());
+        assert!(!current_match.is_crawford);
+    }
+}
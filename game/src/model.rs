@@ -0,0 +1,305 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use rand::Rng;
+
+use crate::cube::Cube;
+use crate::utils::{initialize, opponent, roll_to_dice, Board, GameResult, Move, ResultType, Turn, PLAYER_GUEST, PLAYER_HOST};
+use crate::zobrist::{cube_key, dice_key, KEYS};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GameError {
+    GameOver,
+    AlreadyRolled,
+    NotRolled,
+    DoublePending,
+    NoDoubleOffered,
+    CannotDouble,
+    IllegalMove,
+    MovesLeft
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            GameError::GameOver => "the game is already over",
+            GameError::AlreadyRolled => "the dice were already rolled this turn",
+            GameError::NotRolled => "the dice haven't been rolled yet",
+            GameError::DoublePending => "a double is waiting for an answer",
+            GameError::NoDoubleOffered => "no double was offered",
+            GameError::CannotDouble => "the cube isn't available to this player",
+            GameError::IllegalMove => "the move isn't legal with the remaining dice",
+            GameError::MovesLeft => "the remaining dice can still be played"
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for GameError {}
+
+// Everything that happened in a game, in order, so it can be replayed and analysed
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    // A rolled turn, with the board and cube as they were before the roll
+    Turn{
+        player: usize,
+        roll: [u8;2],
+        board: Board,
+        cube: Cube,
+        moves: Vec<Move>
+    },
+    Double{
+        player: usize,
+        board: Board,
+        cube: Cube
+    },
+    Take{
+        player: usize
+    },
+    Drop{
+        player: usize
+    }
+}
+
+// A single game between host and guest: the board, whose turn it is, the dice and the cube
+#[derive(Clone, Debug)]
+pub struct GameModel {
+    pub board: Board,
+    pub active_player: usize,
+    // The active player's roll, `None` until they roll this turn
+    pub roll: Option<[u8;2]>,
+    // Dice of the roll that are still to be played
+    pub dice: Vec<u8>,
+    // Moves already played with the roll, most recent last
+    pub played: Vec<Move>,
+    pub cube: Cube,
+    pub result: Option<GameResult>,
+    pub history: Vec<Action>
+}
+
+// Two models are the same position when the board, the player to act, the dice left and the cube match,
+// whichever order the moves leading there were played in
+impl PartialEq for GameModel {
+    fn eq(&self, other: &GameModel) -> bool {
+        let mut dice = self.dice.clone();
+        let mut other_dice = other.dice.clone();
+        dice.sort_unstable();
+        other_dice.sort_unstable();
+        self.board == other.board
+            && self.active_player == other.active_player
+            && self.has_rolled() == other.has_rolled()
+            && dice == other_dice
+            && self.cube == other.cube
+    }
+}
+
+impl Eq for GameModel {}
+
+impl Hash for GameModel {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.zobrist());
+    }
+}
+
+impl Default for GameModel {
+    fn default() -> GameModel {
+        GameModel::new()
+    }
+}
+
+impl GameModel {
+    pub fn new() -> GameModel {
+        GameModel{
+            board: initialize(),
+            active_player: PLAYER_HOST,
+            roll: None,
+            dice: vec![],
+            played: vec![],
+            cube: Cube::default(),
+            result: None,
+            history: vec![]
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.result.is_some()
+    }
+
+    pub fn has_rolled(&self) -> bool {
+        self.roll.is_some()
+    }
+
+    // Zobrist key of the whole position, the board's key combined with the side to move, dice and cube
+    pub fn zobrist(&self) -> u64 {
+        let mut key = self.board.zobrist() ^ dice_key(&self.dice) ^ cube_key(self.cube.value, self.cube.owner);
+        if self.active_player == PLAYER_GUEST {
+            key ^= KEYS.side_to_move;
+        }
+        if self.has_rolled() {
+            key ^= KEYS.rolled;
+        }
+        key
+    }
+
+    // Points the winner collects, the result multiplied by the cube
+    pub fn points(&self) -> Option<u32> {
+        self.result.map(|result| result.points() * self.cube.value)
+    }
+
+    pub fn roll_dice(&mut self, roll: [u8;2]) -> Result<(), GameError> {
+        self.check_before_roll()?;
+        self.roll = Some(roll);
+        self.dice = roll_to_dice(roll);
+        self.played.clear();
+        Ok(())
+    }
+
+    pub fn legal_moves(&self) -> Vec<Move> {
+        if self.is_over() || !self.has_rolled() {
+            return vec![];
+        }
+        self.board.legal_moves(self.active_player, &self.dice)
+    }
+
+    pub fn play_move(&mut self, mv: Move) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameOver);
+        }
+        if !self.has_rolled() {
+            return Err(GameError::NotRolled);
+        }
+        if !self.legal_moves().contains(&mv) {
+            return Err(GameError::IllegalMove);
+        }
+
+        self.board.apply_move(self.active_player, &mv);
+        let dice_index = self.dice.iter().position(|die| *die == mv.die).unwrap();
+        self.dice.remove(dice_index);
+        self.played.push(mv);
+        debug_assert!(self.board.validate().is_ok(), "{:?}", self.board.validate());
+        self.result = self.board.result();
+        if self.is_over() {
+            self.record_turn();
+        }
+        Ok(())
+    }
+
+    // Takes back the last move played with the current roll
+    pub fn undo_move(&mut self) -> Option<Move> {
+        if self.is_over() {
+            return None;
+        }
+        let mv = self.played.pop()?;
+        self.board.undo_move(self.active_player, &mv);
+        self.dice.push(mv.die);
+        Some(mv)
+    }
+
+    // Plays a whole turn and passes the dice, the turn must be one of `Board::legal_turns`
+    pub fn play_turn(&mut self, turn: &Turn) -> Result<(), GameError> {
+        for mv in turn.moves.iter() {
+            self.play_move(*mv)?;
+        }
+        if self.is_over() {
+            return Ok(());
+        }
+        self.end_turn()
+    }
+
+    pub fn can_end_turn(&self) -> bool {
+        !self.is_over() && self.has_rolled() && self.legal_moves().is_empty()
+    }
+
+    pub fn end_turn(&mut self) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameOver);
+        }
+        if !self.has_rolled() {
+            return Err(GameError::NotRolled);
+        }
+        if !self.legal_moves().is_empty() {
+            return Err(GameError::MovesLeft);
+        }
+
+        self.record_turn();
+        self.active_player = opponent(self.active_player);
+        self.roll = None;
+        self.dice.clear();
+        self.played.clear();
+        Ok(())
+    }
+
+    // The cube can only be turned by the active player before rolling
+    pub fn offer_double(&mut self) -> Result<(), GameError> {
+        self.check_before_roll()?;
+        if !self.cube.can_double(self.active_player) {
+            return Err(GameError::CannotDouble);
+        }
+        self.history.push(Action::Double{
+            player: self.active_player,
+            board: self.board.clone(),
+            cube: self.cube
+        });
+        self.cube.offer(self.active_player);
+        Ok(())
+    }
+
+    pub fn take_double(&mut self) -> Result<(), GameError> {
+        let Some(offered_by) = self.cube.offered_by else {
+            return Err(GameError::NoDoubleOffered);
+        };
+        self.cube.take();
+        self.history.push(Action::Take{
+            player: opponent(offered_by)
+        });
+        Ok(())
+    }
+
+    // Refusing a double concedes the game at the current cube value
+    pub fn drop_double(&mut self) -> Result<(), GameError> {
+        let offered_by = self.cube.offered_by.take().ok_or(GameError::NoDoubleOffered)?;
+        self.history.push(Action::Drop{
+            player: opponent(offered_by)
+        });
+        self.result = Some(GameResult{
+            winner: offered_by,
+            result_type: ResultType::Single
+        });
+        Ok(())
+    }
+
+    fn record_turn(&mut self) {
+        let Some(roll) = self.roll else {
+            return;
+        };
+        let turn = Turn{
+            moves: self.played.clone()
+        };
+        let mut board = self.board.clone();
+        board.undo_turn(self.active_player, &turn);
+        self.history.push(Action::Turn{
+            player: self.active_player,
+            roll,
+            board,
+            cube: self.cube,
+            moves: turn.moves
+        });
+    }
+
+    fn check_before_roll(&self) -> Result<(), GameError> {
+        if self.is_over() {
+            return Err(GameError::GameOver);
+        }
+        if self.cube.offered_by.is_some() {
+            return Err(GameError::DoublePending);
+        }
+        if self.has_rolled() {
+            return Err(GameError::AlreadyRolled);
+        }
+        Ok(())
+    }
+}
+
+pub fn random_roll<R: Rng + ?Sized>(rng: &mut R) -> [u8;2] {
+    [rng.gen_range(1..=6), rng.gen_range(1..=6)]
+}
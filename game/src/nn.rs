@@ -0,0 +1,379 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::bearoff::bearoff_equity;
+use crate::bot::Evaluator;
+use crate::model::random_roll;
+use crate::search::{Search, SearchConfig};
+use crate::utils::{initialize, opponent, roll_to_dice, to_player_tower_index, Board, GameResult, ResultType, NUTS_PER_PLAYER, PLAYER_GUEST, PLAYER_HOST, TOWERS_COUNT};
+
+// TD-Gammon style evaluator: one hidden layer of sigmoids trained with TD(λ) on self-play games
+// https://www.bkgm.com/articles/tesauro/tdl.html
+
+// Four units per tower for each player, then their bar and borne off nuts
+const TOWER_UNITS: usize = 4;
+pub const INPUTS: usize = 2 * (TOWERS_COUNT * TOWER_UNITS + 2);
+pub const OUTPUTS: usize = 5;
+pub const DEFAULT_HIDDEN: usize = 80;
+pub const WEIGHTS_PATH: &str = "assets/nn/weights.bin";
+const WEIGHTS_MAGIC: &[u8;4] = b"BGNN";
+const WEIGHTS_VERSION: u32 = 1;
+const INITIAL_WEIGHT_RANGE: f32 = 0.1;
+
+// Chances of each outcome for the player who just moved, gammons and backgammons include the lesser results
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Probabilities {
+    pub win: f32,
+    pub win_gammon: f32,
+    pub win_backgammon: f32,
+    pub lose_gammon: f32,
+    pub lose_backgammon: f32
+}
+
+impl Probabilities {
+    // Cubeless equity in points per game
+    pub fn equity(&self) -> f32 {
+        2. * self.win - 1. + self.win_gammon - self.lose_gammon + self.win_backgammon - self.lose_backgammon
+    }
+
+    // The same chances seen by the opponent
+    pub fn flipped(&self) -> Probabilities {
+        Probabilities{
+            win: 1. - self.win,
+            win_gammon: self.lose_gammon,
+            win_backgammon: self.lose_backgammon,
+            lose_gammon: self.win_gammon,
+            lose_backgammon: self.win_backgammon
+        }
+    }
+
+    pub fn from_result(result: GameResult, player: usize) -> Probabilities {
+        let gammon = if result.result_type == ResultType::Single { 0. } else { 1. };
+        let backgammon = if result.result_type == ResultType::Backgammon { 1. } else { 0. };
+        let won = Probabilities{
+            win: 1.,
+            win_gammon: gammon,
+            win_backgammon: backgammon,
+            lose_gammon: 0.,
+            lose_backgammon: 0.
+        };
+        if result.winner == player { won } else { won.flipped() }
+    }
+
+    fn from_outputs(outputs: &[f32;OUTPUTS]) -> Probabilities {
+        Probabilities{
+            win: outputs[0],
+            win_gammon: outputs[1],
+            win_backgammon: outputs[2],
+            lose_gammon: outputs[3],
+            lose_backgammon: outputs[4]
+        }
+    }
+
+    fn to_outputs(self) -> [f32;OUTPUTS] {
+        [self.win, self.win_gammon, self.win_backgammon, self.lose_gammon, self.lose_backgammon]
+    }
+}
+
+// Inputs for the board right after `player` moved, their nuts first, both sides in their own direction of travel
+pub fn encode(board: &Board, player: usize) -> [f32;INPUTS] {
+    let mut inputs = [0.;INPUTS];
+    for (side, side_player) in [player, opponent(player)].into_iter().enumerate() {
+        let offset = side * INPUTS / 2;
+        for global_tower_index in 0..TOWERS_COUNT {
+            let nuts = board.nuts_of(side_player, global_tower_index) as f32;
+            let unit = offset + to_player_tower_index(side_player, global_tower_index) * TOWER_UNITS;
+            inputs[unit] = if nuts >= 1. { 1. } else { 0. };
+            inputs[unit + 1] = if nuts >= 2. { 1. } else { 0. };
+            inputs[unit + 2] = if nuts >= 3. { 1. } else { 0. };
+            inputs[unit + 3] = if nuts > 3. { (nuts - 3.) / 2. } else { 0. };
+        }
+        inputs[offset + TOWERS_COUNT * TOWER_UNITS] = board.bar.nuts(side_player) as f32 / 2.;
+        inputs[offset + TOWERS_COUNT * TOWER_UNITS + 1] = board.borne_off.nuts(side_player) as f32 / NUTS_PER_PLAYER as f32;
+    }
+    inputs
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct TrainingConfig {
+    // Learning rate
+    pub alpha: f32,
+    // Decay of the eligibility traces, how far back a result is credited
+    pub lambda: f32
+}
+
+impl Default for TrainingConfig {
+    fn default() -> TrainingConfig {
+        TrainingConfig{
+            alpha: 0.1,
+            lambda: 0.7
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Network {
+    hidden: usize,
+    // Row per hidden unit, the bias last
+    hidden_weights: Vec<f32>,
+    // Row per output, the bias last
+    output_weights: Vec<f32>
+}
+
+// Activations of one forward pass, kept for the gradients
+struct Activations {
+    inputs: [f32;INPUTS],
+    hidden: Vec<f32>,
+    outputs: [f32;OUTPUTS]
+}
+
+impl Network {
+    pub fn new<R: Rng + ?Sized>(hidden: usize, rng: &mut R) -> Network {
+        let mut weights = |count: usize| (0..count).map(|_| rng.gen_range(-INITIAL_WEIGHT_RANGE..INITIAL_WEIGHT_RANGE)).collect();
+        Network{
+            hidden,
+            hidden_weights: weights(hidden * (INPUTS + 1)),
+            output_weights: weights(OUTPUTS * (hidden + 1))
+        }
+    }
+
+    pub fn probabilities(&self, board: &Board, player: usize) -> Probabilities {
+        Probabilities::from_outputs(&self.forward(encode(board, player)).outputs)
+    }
+
+    fn forward(&self, inputs: [f32;INPUTS]) -> Activations {
+        let hidden: Vec<f32> = self.hidden_weights.chunks(INPUTS + 1).map(|row| {
+            let sum = row[INPUTS] + row.iter().zip(inputs.iter()).filter(|(_, input)| **input != 0.).map(|(weight, input)| weight * input).sum::<f32>();
+            sigmoid(sum)
+        }).collect();
+        let mut outputs = [0.;OUTPUTS];
+        for (output, row) in outputs.iter_mut().zip(self.output_weights.chunks(self.hidden + 1)) {
+            *output = sigmoid(row[self.hidden] + row.iter().zip(hidden.iter()).map(|(weight, unit)| weight * unit).sum::<f32>());
+        }
+        Activations{
+            inputs,
+            hidden,
+            outputs
+        }
+    }
+
+    // Plays one game against itself, learning from every position, and returns how it ended
+    pub fn train_game<R: Rng + ?Sized>(&mut self, config: TrainingConfig, rng: &mut R) -> GameResult {
+        let mut board = initialize();
+        let mut player = if rng.gen_bool(0.5) { PLAYER_HOST } else { PLAYER_GUEST };
+        // Each player learns from their own positions, one full turn apart
+        let mut traces = [Traces::new(self), Traces::new(self)];
+        loop {
+            let dice = roll_to_dice(random_roll(rng));
+            let turn = Search::new(self, SearchConfig::ply(0)).best_turn(&board, player, &dice);
+            board.apply_turn(player, &turn);
+
+            if let Some(result) = board.result() {
+                for trace_player in [PLAYER_HOST, PLAYER_GUEST] {
+                    traces[trace_player].finish(self, Probabilities::from_result(result, trace_player), config);
+                }
+                return result;
+            }
+            traces[player].step(self, &board, player, config);
+            player = opponent(player);
+        }
+    }
+
+    // File layout: magic, version, input and hidden sizes as little endian u32, then every weight as little endian f32
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut bytes: Vec<u8> = WEIGHTS_MAGIC.to_vec();
+        for header in [WEIGHTS_VERSION, INPUTS as u32, self.hidden as u32] {
+            bytes.extend_from_slice(&header.to_le_bytes());
+        }
+        for weight in self.hidden_weights.iter().chain(self.output_weights.iter()) {
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, bytes)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Network> {
+        let bytes = fs::read(path)?;
+        let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+        if bytes.len() < 16 || &bytes[..4] != WEIGHTS_MAGIC {
+            return Err(invalid("not a weights file"));
+        }
+        let header = |index: usize| u32::from_le_bytes(bytes[4 + 4 * index..8 + 4 * index].try_into().unwrap());
+        if header(0) != WEIGHTS_VERSION || header(1) as usize != INPUTS {
+            return Err(invalid("weights were saved for another input encoding"));
+        }
+
+        let hidden = header(2) as usize;
+        let hidden_count = hidden * (INPUTS + 1);
+        let output_count = OUTPUTS * (hidden + 1);
+        if bytes.len() != 16 + 4 * (hidden_count + output_count) {
+            return Err(invalid("the weights file is truncated"));
+        }
+        let mut weights = bytes[16..].chunks(4).map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()));
+        Ok(Network{
+            hidden,
+            hidden_weights: weights.by_ref().take(hidden_count).collect(),
+            output_weights: weights.collect()
+        })
+    }
+}
+
+impl Evaluator for Network {
+    fn evaluate(&self, board: &Board, player: usize) -> f32 {
+        if let Some(result) = board.result() {
+            return Probabilities::from_result(result, player).equity();
+        }
+        if let Some(equity) = bearoff_equity(board, player) {
+            return equity;
+        }
+        self.probabilities(board, player).equity()
+    }
+}
+
+// Eligibility traces of one player, a gradient of every weight for each output
+struct Traces {
+    hidden: Vec<f32>,
+    output: Vec<f32>,
+    previous: Option<[f32;OUTPUTS]>
+}
+
+impl Traces {
+    fn new(network: &Network) -> Traces {
+        Traces{
+            hidden: vec![0.;OUTPUTS * network.hidden_weights.len()],
+            output: vec![0.;network.output_weights.len()],
+            previous: None
+        }
+    }
+
+    // Moves the previous prediction towards `target`, the prediction that followed it or the final result
+    fn learn(&self, network: &mut Network, target: &[f32;OUTPUTS], config: TrainingConfig) {
+        let Some(previous) = self.previous else {
+            return;
+        };
+        let hidden_count = network.hidden_weights.len();
+        let output_row = network.hidden + 1;
+        for output in 0..OUTPUTS {
+            let step = config.alpha * (target[output] - previous[output]);
+            if step == 0. {
+                continue;
+            }
+            let hidden_traces = &self.hidden[output * hidden_count..(output + 1) * hidden_count];
+            for (weight, trace) in network.hidden_weights.iter_mut().zip(hidden_traces.iter()) {
+                *weight += step * trace;
+            }
+            let output_weights = &mut network.output_weights[output * output_row..(output + 1) * output_row];
+            let output_traces = &self.output[output * output_row..(output + 1) * output_row];
+            for (weight, trace) in output_weights.iter_mut().zip(output_traces.iter()) {
+                *weight += step * trace;
+            }
+        }
+    }
+
+    fn step(&mut self, network: &mut Network, board: &Board, player: usize, config: TrainingConfig) {
+        let activations = network.forward(encode(board, player));
+        self.learn(network, &activations.outputs, config);
+
+        // e = λe + ∇y, outputs are sigmoids of the hidden sigmoids
+        let hidden_count = network.hidden_weights.len();
+        let output_row = network.hidden + 1;
+        for trace in self.hidden.iter_mut().chain(self.output.iter_mut()) {
+            *trace *= config.lambda;
+        }
+        for output in 0..OUTPUTS {
+            let value = activations.outputs[output];
+            let output_gradient = value * (1. - value);
+            let output_traces = &mut self.output[output * output_row..(output + 1) * output_row];
+            for (unit, trace) in output_traces.iter_mut().enumerate() {
+                *trace += output_gradient * activations.hidden.get(unit).copied().unwrap_or(1.);
+            }
+
+            let hidden_traces = &mut self.hidden[output * hidden_count..(output + 1) * hidden_count];
+            for (unit, unit_traces) in hidden_traces.chunks_mut(INPUTS + 1).enumerate() {
+                let unit_value = activations.hidden[unit];
+                let gradient = output_gradient * network.output_weights[output * output_row + unit] * unit_value * (1. - unit_value);
+                for (input, trace) in unit_traces.iter_mut().enumerate() {
+                    let input_value = activations.inputs.get(input).copied().unwrap_or(1.);
+                    if input_value != 0. {
+                        *trace += gradient * input_value;
+                    }
+                }
+            }
+        }
+        self.previous = Some(activations.outputs);
+    }
+
+    fn finish(&mut self, network: &mut Network, result: Probabilities, config: TrainingConfig) {
+        self.learn(network, &result.to_outputs(), config);
+        self.previous = None;
+    }
+}
+
+fn sigmoid(value: f32) -> f32 {
+    1. / (1. + (-value).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::utils::player_to_global_tower_index;
+
+    #[test]
+    fn encoding_sees_the_board_from_the_player_to_evaluate() {
+        let start = initialize();
+        // The start is the same position for both players
+        assert_eq!(encode(&start, PLAYER_HOST), encode(&start, PLAYER_GUEST));
+
+        // Two nuts on the 24 point, five on the 13 point
+        let inputs = encode(&start, PLAYER_HOST);
+        assert_eq!(inputs[..TOWER_UNITS], [1., 1., 0., 0.]);
+        assert_eq!(inputs[11 * TOWER_UNITS..12 * TOWER_UNITS], [1., 1., 1., 1.]);
+
+        let mut board = start.clone();
+        board.towers[player_to_global_tower_index(PLAYER_GUEST, 0)].nuts -= 1;
+        board.bar.guest_nuts = 1;
+        board.rehash();
+        let host_inputs = encode(&board, PLAYER_HOST);
+        let guest_inputs = encode(&board, PLAYER_GUEST);
+        // The evaluated player's units come first, the opponent's in the second half
+        assert_eq!(host_inputs[INPUTS / 2 + TOWERS_COUNT * TOWER_UNITS], 0.5);
+        assert_eq!(guest_inputs[TOWERS_COUNT * TOWER_UNITS], 0.5);
+        assert_eq!(guest_inputs[..TOWER_UNITS], [1., 0., 0., 0.]);
+        assert_eq!(host_inputs[INPUTS / 2..INPUTS / 2 + TOWER_UNITS], [1., 0., 0., 0.]);
+    }
+
+    #[test]
+    fn weights_round_trip_through_a_file() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let network = Network::new(8, &mut rng);
+        let path = env::temp_dir().join(format!("backgammon-weights-{}.bin", std::process::id()));
+        network.save(&path).unwrap();
+        let loaded = Network::load(&path);
+
+        // A cut off file is refused instead of loading half a network
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() - 4]).unwrap();
+        let truncated = Network::load(&path);
+        fs::write(&path, b"not weights").unwrap();
+        let garbage = Network::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.hidden, network.hidden);
+        assert_eq!(loaded.hidden_weights, network.hidden_weights);
+        assert_eq!(loaded.output_weights, network.output_weights);
+        assert_eq!(loaded.probabilities(&initialize(), PLAYER_HOST), network.probabilities(&initialize(), PLAYER_HOST));
+        assert_eq!(truncated.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(garbage.unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+}
@@ -1,11 +1,20 @@
+use std::sync::{mpsc::Receiver, Mutex};
+
+use tokio::task::JoinHandle;
+
 use bevy::{prelude::*, render::camera::ScalingMode, sprite::Anchor, window::{PresentMode, WindowMode, WindowTheme}};
-use crate::utils::Board;
+use crate::model::GameModel;
+use crate::match_play::Match;
+use crate::bot::{Bot, Difficulty};
+use crate::analysis::Analysis;
+use crate::utils::Turn;
 use crate::components::ButtonAssets;
-use crate::gdk::GDK;
+use crate::gdk::{GDK,GameEvent};
+use aptos_sdk::types::account_address::AccountAddress;
 
 #[derive(Resource, Default)]
 pub struct Game{    
-    pub board:Option<Board>,
+    pub model:Option<GameModel>,
     pub wooden_stack_texture: Handle<Image>,
     pub white_stack_texture: Handle<Image>,
     pub wooden_nut_texture: Handle<Image>,
@@ -13,10 +22,78 @@ pub struct Game{
     pub host_button_assets :Option<ButtonAssets>,
     pub join_button_assets: Option<ButtonAssets>,        
     pub lato_regular_font: Handle<Font>,
-    pub host_addr: Option<String>
+    // Game to join, typed in the main menu
+    pub host_addr: Option<AccountAddress>,
+    // Match length picked in the main menu
+    pub match_length: u32,
+    pub current_match: Option<Match>,
+    // Level of the computer opponent picked in the main menu
+    pub difficulty: Difficulty,
+    // The computer opponent when playing against it
+    pub bot: Option<Bot>,
+    pub review: Option<Review>,
+    pub hint: Option<Hint>,
+    // The player's seat in the on-chain game on the board, None for local games
+    pub chain_player: Option<usize>
+}
+
+// The analysed game shown in the review, with the decision on the board
+pub struct Review {
+    pub analysis: Analysis,
+    pub selected: usize
+}
+
+impl Game {
+    pub fn start_match(&mut self) {
+        let current_match = Match::new(self.match_length);
+        self.model = Some(current_match.new_game());
+        self.current_match = Some(current_match);
+        self.chain_player = None;
+    }
+
+    pub fn start_next_game(&mut self) {
+        if let Some(current_match) = self.current_match.as_ref() {
+            self.model = Some(current_match.new_game());
+        }
+    }
+
+    // The hint for the position on the board, older hints are stale once a nut moved
+    pub fn current_hint(&self) -> Option<&Hint> {
+        let key = self.model.as_ref()?.zobrist();
+        self.hint.as_ref().filter(|hint| hint.key == key)
+    }
+}
+
+// Best turns for the dice left to play, with their equities
+pub struct Hint {
+    // `GameModel::zobrist` of the position the turns were ranked for
+    pub key: u64,
+    pub turns: Vec<(Turn, f32)>,
+    // Turn shown on the board instead of the current position
+    pub preview: Option<usize>
 }
 
 #[derive(Resource)]
 pub struct Backend{
-    pub gdk: GDK
+    pub gdk: GDK,
+    // Octas on the player's account when the game started
+    pub balance: Option<u64>,
+    // Why the account couldn't be funded, on-chain games are unavailable then
+    pub funding_error: Option<String>,
+    // An imported account is being checked and funded, `balance` and `funding_error` are unknown until then
+    pub funding_pending: bool
+}
+
+// Events of the on-chain game being played, from the watcher polling the node. Removing the resource
+// stops the watcher
+#[derive(Resource)]
+pub struct ChainWatcher{
+    pub events: Mutex<Receiver<GameEvent>>,
+    pub task: JoinHandle<()>
+}
+
+impl Drop for ChainWatcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
 }
\ No newline at end of file
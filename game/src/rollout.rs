@@ -0,0 +1,245 @@
+use std::thread;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::bot::Evaluator;
+use crate::model::random_roll;
+use crate::nn::Probabilities;
+use crate::search::{Search, SearchConfig};
+use crate::utils::{opponent, roll_to_dice, Board, GameResult, Turn};
+
+// Ordered rolls of two dice, the first roll of trial `i` is the `i % 36`th so every 36 trials start with each roll once
+const ORDERED_ROLLS: u64 = 36;
+
+// Plays a position out to the end many times, both sides moving with the same search
+#[derive(Copy, Clone, Debug)]
+pub struct RolloutConfig {
+    // A multiple of 36 keeps the first rolls exactly balanced
+    pub trials: u64,
+    // Trial `i` is played with its own generator seeded from `seed` and `i`, so rolling out
+    // several candidate moves with the same seed gives them the same dice
+    pub seed: u64,
+    // 0 uses every core
+    pub threads: usize,
+    pub search: SearchConfig,
+    // Candidate turns rolled out by `rollout_turns`
+    pub candidates: usize
+}
+
+impl Default for RolloutConfig {
+    fn default() -> RolloutConfig {
+        RolloutConfig{
+            trials: 1296,
+            seed: 0,
+            threads: 0,
+            search: SearchConfig::ply(0),
+            candidates: 4
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RolloutResult {
+    pub trials: u64,
+    // Cubeless points per game for the player who just moved
+    pub equity: f32,
+    pub standard_error: f32,
+    pub probabilities: Probabilities
+}
+
+// Sums of one thread's trials
+#[derive(Default)]
+struct Totals {
+    trials: u64,
+    points: f64,
+    squared_points: f64,
+    outcomes: [f64;5]
+}
+
+impl Totals {
+    fn add(&mut self, result: GameResult, player: usize) {
+        let probabilities = Probabilities::from_result(result, player);
+        let points = probabilities.equity() as f64;
+        self.trials += 1;
+        self.points += points;
+        self.squared_points += points * points;
+        let outcomes = [probabilities.win, probabilities.win_gammon, probabilities.win_backgammon, probabilities.lose_gammon, probabilities.lose_backgammon];
+        for (total, outcome) in self.outcomes.iter_mut().zip(outcomes) {
+            *total += outcome as f64;
+        }
+    }
+
+    fn merge(&mut self, other: Totals) {
+        self.trials += other.trials;
+        self.points += other.points;
+        self.squared_points += other.squared_points;
+        for (total, outcome) in self.outcomes.iter_mut().zip(other.outcomes) {
+            *total += outcome;
+        }
+    }
+
+    fn result(&self) -> RolloutResult {
+        if self.trials == 0 {
+            return RolloutResult::default();
+        }
+        let count = self.trials as f64;
+        let mean = self.points / count;
+        let variance = if self.trials > 1 {
+            ((self.squared_points - count * mean * mean) / (count - 1.)).max(0.)
+        } else {
+            0.
+        };
+        let average = |index: usize| (self.outcomes[index] / count) as f32;
+        RolloutResult{
+            trials: self.trials,
+            equity: mean as f32,
+            standard_error: (variance / count).sqrt() as f32,
+            probabilities: Probabilities{
+                win: average(0),
+                win_gammon: average(1),
+                win_backgammon: average(2),
+                lose_gammon: average(3),
+                lose_backgammon: average(4)
+            }
+        }
+    }
+}
+
+// Rolls out `board` right after `player` moved, the opponent rolling first
+pub fn rollout(board: &Board, player: usize, evaluator: &dyn Evaluator, config: RolloutConfig) -> RolloutResult {
+    let threads = if config.threads == 0 {
+        thread::available_parallelism().map_or(1, |count| count.get())
+    } else {
+        config.threads
+    };
+
+    let mut totals = Totals::default();
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads as u64).map(|worker| {
+            scope.spawn(move || {
+                let mut search = Search::new(evaluator, config.search);
+                let mut totals = Totals::default();
+                for trial in (worker..config.trials).step_by(threads) {
+                    let mut rng = StdRng::seed_from_u64(trial_seed(config.seed, trial));
+                    let quasi_random = trial % ORDERED_ROLLS;
+                    let first_roll = [(quasi_random / 6 + 1) as u8, (quasi_random % 6 + 1) as u8];
+                    totals.add(play_out(board, player, first_roll, &mut search, &mut rng), player);
+                }
+                totals
+            })
+        }).collect();
+        for worker in workers {
+            totals.merge(worker.join().unwrap());
+        }
+    });
+    totals.result()
+}
+
+// Equity of the player on roll, before the dice are thrown
+pub fn rollout_on_roll(board: &Board, player: usize, evaluator: &dyn Evaluator, config: RolloutConfig) -> RolloutResult {
+    let result = rollout(board, opponent(player), evaluator, config);
+    RolloutResult{
+        equity: -result.equity,
+        probabilities: result.probabilities.flipped(),
+        ..result
+    }
+}
+
+// The best few turns for the dice by the search, rolled out with the same dice and sorted best first
+pub fn rollout_turns(board: &Board, player: usize, dice: &[u8], evaluator: &dyn Evaluator, config: RolloutConfig) -> Vec<(Turn, RolloutResult)> {
+    let candidates: Vec<Turn> = Search::new(evaluator, config.search).rank_turns(board, player, dice).into_iter()
+        .take(config.candidates.max(1))
+        .map(|(turn, _)| turn)
+        .collect();
+    let mut scratch = board.clone();
+    let mut results: Vec<(Turn, RolloutResult)> = candidates.into_iter().map(|turn| {
+        scratch.apply_turn(player, &turn);
+        let result = rollout(&scratch, player, evaluator, config);
+        scratch.undo_turn(player, &turn);
+        (turn, result)
+    }).collect();
+    results.sort_by(|(_, first), (_, second)| second.equity.total_cmp(&first.equity));
+    results
+}
+
+fn play_out(board: &Board, player: usize, first_roll: [u8;2], search: &mut Search, rng: &mut StdRng) -> GameResult {
+    let mut board = board.clone();
+    let mut on_roll = opponent(player);
+    let mut roll = first_roll;
+    loop {
+        if let Some(result) = board.result() {
+            return result;
+        }
+        let turn = search.best_turn(&board, on_roll, &roll_to_dice(roll));
+        board.apply_turn(on_roll, &turn);
+        on_roll = opponent(on_roll);
+        roll = random_roll(rng);
+    }
+}
+
+// splitmix64 of the trial index, so neighbouring trials get unrelated streams
+fn trial_seed(seed: u64, trial: u64) -> u64 {
+    let mut z = seed.wrapping_add(trial.wrapping_add(1).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::HeuristicEvaluator;
+    use crate::utils::{player_to_global_tower_index, BorneOff, Tower, PLAYER_GUEST, PLAYER_HOST, TOWERS_COUNT};
+
+    // Both players with `nuts` on their 6 point and the rest borne off
+    fn race(nuts: u8) -> Board {
+        let mut board = Board::empty();
+        for player in [PLAYER_HOST, PLAYER_GUEST] {
+            board.towers[player_to_global_tower_index(player, TOWERS_COUNT - 6)] = Tower{ nuts, owner: player as u8 };
+        }
+        board.borne_off = BorneOff{
+            host_nuts: 15 - nuts,
+            guest_nuts: 15 - nuts
+        };
+        board.rehash();
+        board
+    }
+
+    #[test]
+    fn rollouts_repeat_with_the_same_seed() {
+        let evaluator = HeuristicEvaluator::default();
+        let config = RolloutConfig{
+            trials: 144,
+            seed: 11,
+            threads: 1,
+            ..RolloutConfig::default()
+        };
+        let board = race(4);
+        let first = rollout(&board, PLAYER_HOST, &evaluator, config);
+        assert_eq!(first.trials, 144);
+        assert!(first.standard_error > 0.);
+
+        // Each trial has its own generator, so how they are spread over threads doesn't matter
+        for threads in [1, 3] {
+            let again = rollout(&board, PLAYER_HOST, &evaluator, RolloutConfig{ threads, ..config });
+            assert_eq!(again.equity, first.equity);
+            assert_eq!(again.standard_error, first.standard_error);
+            assert_eq!(again.probabilities, first.probabilities);
+        }
+    }
+
+    #[test]
+    fn a_sure_win_rolls_out_without_error() {
+        let evaluator = HeuristicEvaluator::default();
+        let mut board = race(1);
+        // The guest's last nut is on their ace point, so they bear it off with any first roll
+        board.towers[player_to_global_tower_index(PLAYER_GUEST, TOWERS_COUNT - 6)] = Tower::default();
+        board.towers[player_to_global_tower_index(PLAYER_GUEST, TOWERS_COUNT - 1)] = Tower{ nuts: 1, owner: PLAYER_GUEST as u8 };
+        board.rehash();
+        let result = rollout_on_roll(&board, PLAYER_GUEST, &evaluator, RolloutConfig{ trials: 36, ..RolloutConfig::default() });
+        assert_eq!(result.equity, 1.);
+        assert_eq!(result.standard_error, 0.);
+        assert_eq!(result.probabilities.win, 1.);
+    }
+}
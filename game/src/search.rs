@@ -0,0 +1,238 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use crate::bot::Evaluator;
+use crate::utils::{opponent, roll_to_dice, Board, Turn};
+
+// The 21 distinct rolls, doubles come up once in 36 and the others twice
+pub const ROLLS: [[u8;2];21] = [
+    [1, 1], [2, 2], [3, 3], [4, 4], [5, 5], [6, 6],
+    [1, 2], [1, 3], [1, 4], [1, 5], [1, 6],
+    [2, 3], [2, 4], [2, 5], [2, 6],
+    [3, 4], [3, 5], [3, 6],
+    [4, 5], [4, 6],
+    [5, 6]
+];
+
+// Ways to throw the roll out of the 36
+pub fn roll_weight(roll: [u8;2]) -> f32 {
+    if roll[0] == roll[1] { 1. } else { 2. }
+}
+
+pub fn roll_probability(roll: [u8;2]) -> f32 {
+    roll_weight(roll) / 36.
+}
+
+// How far ahead to look and how hard to prune between plies.
+// Candidates are ranked with the static evaluation, only the best few within `margin` of the best are searched deeper
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SearchConfig {
+    // 0 is the static evaluation, every ply adds the opponent's 21 replies
+    pub depth: u8,
+    // Moves searched deeper for the player choosing a turn
+    pub root_candidates: usize,
+    pub root_margin: f32,
+    // Replies searched deeper for each opponent roll
+    pub reply_candidates: usize,
+    pub reply_margin: f32
+}
+
+impl SearchConfig {
+    pub fn ply(depth: u8) -> SearchConfig {
+        SearchConfig{
+            depth,
+            ..SearchConfig::default()
+        }
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> SearchConfig {
+        SearchConfig{
+            depth: 1,
+            root_candidates: 8,
+            root_margin: 0.16,
+            reply_candidates: 2,
+            reply_margin: 0.08
+        }
+    }
+}
+
+// Expectimax over the dice: a player picks the turn with the highest value, chance nodes average the 21 rolls
+pub struct Search<'a> {
+    evaluator: &'a dyn Evaluator,
+    config: SearchConfig,
+    // Values by position key, the player who just moved and the depth they were searched to
+    cache: HashMap<(u64, usize, u8), f32>
+}
+
+impl<'a> Search<'a> {
+    pub fn new(evaluator: &'a dyn Evaluator, config: SearchConfig) -> Search<'a> {
+        Search{
+            evaluator,
+            config,
+            cache: HashMap::new()
+        }
+    }
+
+    pub fn config(&self) -> SearchConfig {
+        self.config
+    }
+
+    // Equity of `board` right after `player` moved, looking `depth` plies ahead
+    pub fn evaluate(&mut self, board: &Board, player: usize, depth: u8) -> f32 {
+        if let Some(result) = board.result() {
+            let points = result.points() as f32;
+            return if result.winner == player { points } else { -points };
+        }
+        if depth == 0 {
+            return self.evaluator.evaluate(board, player);
+        }
+
+        let key = (board.zobrist(), player, depth);
+        if let Some(value) = self.cache.get(&key) {
+            return *value;
+        }
+
+        let other = opponent(player);
+        let mut value = 0.;
+        for roll in ROLLS {
+            let dice = roll_to_dice(roll);
+            let candidates = self.candidates(board, other, &dice, self.config.reply_candidates, self.config.reply_margin);
+            let best_reply = self.best_of(board, other, candidates, depth - 1);
+            value -= roll_weight(roll) * best_reply;
+        }
+        // Weighting by 36ths keeps a sure result exact, rounded probabilities would rank a slower win above a win now
+        value /= 36.;
+        self.cache.insert(key, value);
+        value
+    }
+
+    // Equity of the player on roll, before the dice are thrown
+    pub fn evaluate_on_roll(&mut self, board: &Board, player: usize) -> f32 {
+        -self.evaluate(board, opponent(player), self.config.depth)
+    }
+
+    // Every legal turn with its equity, best first. Turns pruned after the static pass keep their static equity
+    pub fn rank_turns(&mut self, board: &Board, player: usize, dice: &[u8]) -> Vec<(Turn, f32)> {
+        let mut ranked = self.ranked_statically(board, player, dice);
+        if self.config.depth == 0 {
+            return ranked;
+        }
+
+        let kept = kept_count(&ranked, self.config.root_candidates, self.config.root_margin);
+        let mut scratch = board.clone();
+        for (turn, equity) in ranked[..kept].iter_mut() {
+            scratch.apply_turn(player, turn);
+            *equity = self.evaluate(&scratch, player, self.config.depth);
+            scratch.undo_turn(player, turn);
+        }
+        // Deeper equities aren't comparable with static ones, searched turns stay ahead of pruned ones
+        ranked[..kept].sort_by(|(_, first), (_, second)| second.partial_cmp(first).unwrap_or(Ordering::Equal));
+        ranked
+    }
+
+    // The best turn for the dice, an empty turn when no nut can move
+    pub fn best_turn(&mut self, board: &Board, player: usize, dice: &[u8]) -> Turn {
+        self.rank_turns(board, player, dice).into_iter().next().map(|(turn, _)| turn).unwrap_or_default()
+    }
+
+    fn ranked_statically(&mut self, board: &Board, player: usize, dice: &[u8]) -> Vec<(Turn, f32)> {
+        let mut scratch = board.clone();
+        let mut ranked: Vec<(Turn, f32)> = board.unique_turns(player, dice).into_iter().map(|turn| {
+            scratch.apply_turn(player, &turn);
+            let equity = self.evaluate(&scratch, player, 0);
+            scratch.undo_turn(player, &turn);
+            (turn, equity)
+        }).collect();
+        ranked.sort_by(|(_, first), (_, second)| second.partial_cmp(first).unwrap_or(Ordering::Equal));
+        ranked
+    }
+
+    fn candidates(&mut self, board: &Board, player: usize, dice: &[u8], count: usize, margin: f32) -> Vec<(Turn, f32)> {
+        let mut ranked = self.ranked_statically(board, player, dice);
+        ranked.truncate(kept_count(&ranked, count, margin));
+        ranked
+    }
+
+    // Value of the best candidate searched to `depth`, candidates come with their static equity
+    fn best_of(&mut self, board: &Board, player: usize, candidates: Vec<(Turn, f32)>, depth: u8) -> f32 {
+        if depth == 0 {
+            return candidates.first().map_or_else(|| self.evaluate(board, player, 0), |(_, equity)| *equity);
+        }
+        let mut scratch = board.clone();
+        let mut best = f32::NEG_INFINITY;
+        for (turn, _) in candidates.iter() {
+            scratch.apply_turn(player, turn);
+            best = best.max(self.evaluate(&scratch, player, depth));
+            scratch.undo_turn(player, turn);
+        }
+        best
+    }
+}
+
+// How many of the ranked turns are worth a deeper look
+fn kept_count(ranked: &[(Turn, f32)], count: usize, margin: f32) -> usize {
+    let Some((_, best)) = ranked.first() else {
+        return 0;
+    };
+    ranked.iter().take(count.max(1)).take_while(|(_, equity)| best - equity <= margin).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::{HeuristicEvaluator, HeuristicWeights};
+    use crate::utils::{initialize, player_to_global_tower_index, BorneOff, Tower, OFF_INDEX, PLAYER_GUEST, PLAYER_HOST, TOWERS_COUNT};
+
+    // The host with a nut on each of `host_points` against the guest's last nut on their ace point
+    fn race(host_points: &[usize]) -> Board {
+        let mut board = Board::empty();
+        for point in host_points {
+            board.towers[player_to_global_tower_index(PLAYER_HOST, TOWERS_COUNT - point)] = Tower{ nuts: 1, owner: PLAYER_HOST as u8 };
+        }
+        board.towers[player_to_global_tower_index(PLAYER_GUEST, TOWERS_COUNT - 1)] = Tower{ nuts: 1, owner: PLAYER_GUEST as u8 };
+        board.borne_off = BorneOff{
+            host_nuts: 15 - host_points.len() as u8,
+            guest_nuts: 14
+        };
+        board.rehash();
+        assert_eq!(board.validate(), Ok(()));
+        board
+    }
+
+    fn heuristic() -> HeuristicEvaluator {
+        HeuristicEvaluator{
+            weights: HeuristicWeights::default()
+        }
+    }
+
+    #[test]
+    fn search_bears_off_the_last_nuts() {
+        let board = race(&[2, 1]);
+        let evaluator = heuristic();
+        for depth in 0..=2 {
+            let mut search = Search::new(&evaluator, SearchConfig::ply(depth));
+            let ranked = search.rank_turns(&board, PLAYER_HOST, &[2, 1]);
+            assert!(ranked.len() > 1);
+
+            let (best, equity) = &ranked[0];
+            assert!(best.moves.iter().all(|mv| mv.to == OFF_INDEX), "depth {} played {:?}", depth, best);
+            assert_eq!(*equity, 1.);
+            // Moving 2/1 first leaves a nut behind, which the guest's next roll beats
+            assert!(ranked[1..].iter().all(|(_, other)| other < equity));
+            if depth > 0 {
+                assert_eq!(ranked[1].1, -1.);
+            }
+        }
+    }
+
+    #[test]
+    fn search_scores_a_sure_win_on_roll() {
+        let evaluator = heuristic();
+        let mut search = Search::new(&evaluator, SearchConfig::ply(1));
+        // Every roll bears the last nut off
+        assert_eq!(search.evaluate_on_roll(&race(&[1]), PLAYER_HOST), 1.);
+        assert!(search.evaluate_on_roll(&initialize(), PLAYER_HOST).abs() < 1.);
+    }
+}
@@ -6,5 +6,15 @@ pub enum GameState{
     MainMenu,
     CreatingGame,
     JoiningGame,    
-    InGame
+    InGame,
+    // The active player doubled and the opponent has to take or pass
+    DoubleOffered,
+    // A game was decided, showing its result and the match score
+    GameOver,
+    // Going through the analysis of the game that just ended
+    Review,
+    // The player's account, its key shown and another one importable
+    Wallet,
+    // The account has no APT for gas and the faucet couldn't help, only local games can be played
+    FundingFailed
 }
\ No newline at end of file
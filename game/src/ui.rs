@@ -2,15 +2,37 @@
 use std::{any::Any, borrow::{Borrow, BorrowMut}};
 
 use bevy::{prelude::*, render::camera::ScalingMode, sprite::Anchor, window::{PresentMode, WindowMode, WindowTheme}};
-use bevy_simple_text_input::{TextInput, TextInputPlugin, TextInputSubmitEvent};
+use bevy_simple_text_input::{TextInput, TextInputPlugin, TextInputSubmitEvent, TextInputValue};
 
 use crate::gdk::GDK;
 use crate::resources::{Backend,Game};
 use crate::states::GameState;
 use crate::components::*;
+use crate::drawing::player_name;
+use crate::resources::Review;
+use crate::analysis::{Decision, DecisionKind, PlayerSummary};
+use crate::utils::turn_notation;
+use crate::match_play::MATCH_LENGTHS;
+use crate::gnubg::game_from_id;
+use crate::bot::{best_available_evaluator, Bot, Difficulty};
+use crate::utils::PLAYER_GUEST;
+use crate::wallet;
+
+use anyhow::Result;
+use bevy_async_task::{AsyncTaskRunner, AsyncTaskStatus};
+use aptos_sdk::types::account_address::AccountAddress;
+use std::str::FromStr;
 
 
 const MENU_BACKGROUND_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const TEXT_BUTTON_COLOR: Color = Color::rgb(0.3, 0.3, 0.3);
+const TEXT_BUTTON_HOVER_COLOR: Color = Color::rgb(0.4, 0.4, 0.4);
+const TEXT_BUTTON_SELECTED_COLOR: Color = Color::rgb(0.35, 0.55, 0.35);
+const OCTAS_PER_APT: f64 = 100_000_000.;
+const REVIEW_PANEL_WIDTH: f32 = 175.;
+const REVIEW_FONT_SIZE: f32 = 14.;
+// Decisions listed at once, the page holding the selected one is shown
+const REVIEW_PAGE_SIZE: usize = 18;
 
 #[derive(Bundle)]
 struct QButtonBundle {   
@@ -24,9 +46,24 @@ pub fn setup_menu(mut commands: Commands,mut game: ResMut<Game>){
     build_buttons(commands.borrow_mut(),game.host_button_assets.clone().unwrap(),game.join_button_assets.clone().unwrap(),game.lato_regular_font.clone());
 }
 
-pub fn input_listener(mut events: EventReader<TextInputSubmitEvent>) {
+// A GNU Backgammon "PositionID:MatchID" pasted in its own box starts a local game from that position
+pub fn input_listener(mut events: EventReader<TextInputSubmitEvent>,inputs: Query<&Id>,mut game: ResMut<Game>,mut next_state: ResMut<NextState<GameState>>) {
     for event in events.read() {
         info!("{:?} submitted: {}", event.entity, event.value);
+        if !inputs.get(event.entity).is_ok_and(|id| id.id == "position_input") {
+            continue;
+        }
+        if let Ok((model, current_match)) = game_from_id(&event.value) {
+            let double_offered = model.cube.offered_by.is_some();
+            game.model = Some(model);
+            game.current_match = current_match;
+            // Nothing carries over from the previous game, not even a computer opponent
+            game.bot = None;
+            game.hint = None;
+            game.review = None;
+            game.chain_player = None;
+            next_state.set(if double_offered { GameState::DoubleOffered } else { GameState::InGame });
+        }
     }
 }
 
@@ -64,11 +101,36 @@ fn create_button(parent:&mut ChildBuilder,id: &str,assets: ButtonAssets){
     // });    
 }
 
-fn create_text_input(parent:&mut ChildBuilder,font: Handle<Font>){
+// Buttons without sprite assets, just a label on a colored background
+fn create_text_button(parent:&mut ChildBuilder,id: &str,label: &str,font: Handle<Font>){
+    let button = ButtonBundle {
+        style: Style {
+            height: Val::Px(65.0),
+            padding: UiRect::horizontal(Val::Px(10.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: TEXT_BUTTON_COLOR.into(),
+        ..default()
+    };
+    let text = TextBundle::from_section(label, TextStyle {
+        font: font,
+        font_size: 24.,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    });
+
+    parent.spawn((Id::new(id.to_string()), button)).with_children(|parent| {
+        parent.spawn(text);
+    });
+}
+
+fn create_text_input(parent:&mut ChildBuilder,id: &str,font: Handle<Font>,width: f32){
     parent.spawn((
+        Id::new(id.to_string()),
         NodeBundle {
             style: Style {
-                width: Val::Px(200.0),
+                width: Val::Px(width),
                 border: UiRect::all(Val::Px(5.0)),
                 padding: UiRect::all(Val::Px(5.0)),
                 ..default()
@@ -105,10 +167,35 @@ fn build_buttons(commands: &mut Commands,host_button_assets: ButtonAssets,join_b
     .with_children(|parent| {
         create_button(parent,"host_button", host_button_assets);
         create_button(parent,"join_button",join_button_assets);
-        create_text_input(parent,font);
+        create_text_input(parent,"host_address_input",font.clone(),200.);
+        create_text_input(parent,"position_input",font.clone(),300.);
+        for length in MATCH_LENGTHS {
+            create_text_button(parent, &format!("match_length_{}", length), &format!("{} pt", length), font.clone());
+        }
+        create_text_button(parent, "local_match_button", "Local match", font.clone());
+        create_text_button(parent, "computer_match_button", "Play vs Computer", font.clone());
+        // The computer's level, stacked next to its button
+        parent.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(5.),
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            for difficulty in Difficulty::ALL {
+                create_text_button(parent, &difficulty_id(difficulty), difficulty.name(), font.clone());
+            }
+        });
+        create_text_button(parent, "wallet_button", "Wallet", font.clone());
     });    
 }
 
+fn difficulty_id(difficulty: Difficulty) -> String {
+    format!("difficulty_{}", difficulty.name())
+}
+
 pub fn update_mainmenu(
     mut interaction_query: Query<
         (
@@ -122,6 +209,9 @@ pub fn update_mainmenu(
         ),
         (Changed<Interaction>, With<Button>),
     >,
+    inputs: Query<(&Id, &TextInputValue)>,
+    backend: Res<Backend>,
+    mut game: ResMut<Game>,
     mut next_state: ResMut<NextState<GameState>>,    
 ) {  
     
@@ -135,13 +225,24 @@ pub fn update_mainmenu(
                 image.texture = assets.hover.clone();
             },
             Interaction::Pressed =>{
-                if id.id == "host_button"{
+                // Without gas the transactions would only fail
+                if (id.id == "host_button" || id.id == "join_button") && backend.funding_pending {
+                    println!("Still checking the account's balance");
+                }else if (id.id == "host_button" || id.id == "join_button") && backend.funding_error.is_some() {
+                    next_state.set(GameState::FundingFailed);
+                }else if id.id == "host_button"{
                     next_state.set(GameState::CreatingGame);
                     println!("Host button pressed");
-                }
-                if id.id == "join_button"{
-                    next_state.set(GameState::JoiningGame);
-                    println!("Join button pressed");
+                }else if id.id == "join_button"{
+                    // The host's address, typed in the box next to the buttons
+                    let typed = inputs.iter().find(|(input_id, _)| input_id.id == "host_address_input").map(|(_, value)| value.0.trim().to_string()).unwrap_or_default();
+                    match AccountAddress::from_str(&typed) {
+                        Ok(address) => {
+                            game.host_addr = Some(address);
+                            next_state.set(GameState::JoiningGame);
+                        }
+                        Err(_) => println!("Type the address of the game's host to join it, not {:?}", typed)
+                    }
                 }
                 image.texture = assets.pressed.clone();
                 
@@ -151,3 +252,313 @@ pub fn update_mainmenu(
     }    
 }
 
+pub fn update_text_buttons(
+    interaction_query: Query<(&Id, &Interaction), (Changed<Interaction>, With<Button>, Without<ButtonAssets>)>,
+    mut color_query: Query<(&Id, &Interaction, &mut BackgroundColor), (With<Button>, Without<ButtonAssets>)>,
+    mut game: ResMut<Game>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    for (id, interaction) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        if let Some(length) = id.id.strip_prefix("match_length_") {
+            game.match_length = length.parse().unwrap();
+        }
+        if let Some(difficulty) = id.id.strip_prefix("difficulty_").and_then(Difficulty::from_name) {
+            game.difficulty = difficulty;
+        }
+        if id.id == "local_match_button" {
+            game.bot = None;
+            game.start_match();
+            next_state.set(GameState::InGame);
+        }
+        if id.id == "wallet_button" {
+            next_state.set(GameState::Wallet);
+        }
+        // The computer plays the guest, so the human always starts
+        if id.id == "computer_match_button" {
+            game.bot = Some(Bot::with_difficulty(PLAYER_GUEST, best_available_evaluator(), game.difficulty));
+            game.start_match();
+            next_state.set(GameState::InGame);
+        }
+    }
+
+    // The picked match length and difficulty stay highlighted
+    let selected_ids = [format!("match_length_{}", game.match_length), difficulty_id(game.difficulty)];
+    for (id, interaction, mut color) in &mut color_query {
+        *color = if *interaction == Interaction::Hovered {
+            TEXT_BUTTON_HOVER_COLOR.into()
+        } else if selected_ids.contains(&id.id) {
+            TEXT_BUTTON_SELECTED_COLOR.into()
+        } else {
+            TEXT_BUTTON_COLOR.into()
+        };
+    }
+}
+
+fn game_over_text(game: &Game) -> String {
+    let model = game.model.as_ref().unwrap();
+    let mut lines: Vec<String> = vec![];
+    if let Some(result) = model.result {
+        lines.push(format!("{} wins {} point(s)", player_name(result.winner), model.points().unwrap()));
+    }
+
+    match game.current_match.as_ref() {
+        Some(current_match) => {
+            lines.push(format!("{} point match: Host {} - Guest {}", current_match.length, current_match.score[0], current_match.score[1]));
+            if let Some(winner) = current_match.winner() {
+                lines.push(format!("{} wins the match", player_name(winner)));
+                lines.push("Space: main menu".to_string());
+            } else {
+                if current_match.is_crawford {
+                    lines.push("Next game is the Crawford game, no doubling".to_string());
+                }
+                lines.push("Space: next game".to_string());
+            }
+        },
+        None => lines.push("Space: main menu".to_string())
+    }
+    lines.push("R: review the game".to_string());
+    lines.join("\n")
+}
+
+pub fn setup_game_over(mut commands: Commands,game: Res<Game>){
+    commands.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        background_color: BackgroundColor::from(MENU_BACKGROUND_COLOR),
+        ..default()
+    })
+    .with_children(|parent| {
+        parent.spawn(TextBundle::from_section(game_over_text(&game), TextStyle {
+            font: game.lato_regular_font.clone(),
+            font_size: 32.,
+            color: Color::rgb(0.9, 0.9, 0.9),
+        }));
+    });
+}
+
+fn balance_text(backend: &Backend) -> String {
+    if backend.funding_pending {
+        return "checking...".to_string();
+    }
+    match (backend.balance, backend.funding_error.as_ref()) {
+        (Some(balance), _) => format!("{:.4} APT", balance as f64 / OCTAS_PER_APT),
+        (None, Some(error)) => format!("unknown, {}", error),
+        (None, None) => "unknown".to_string()
+    }
+}
+
+fn wallet_text(backend: &Backend, message: &str) -> String {
+    let storage = if backend.gdk.is_account_saved() {
+        format!("Saved in {}", wallet::key_path().display())
+    } else {
+        format!("Temporary account, {} couldn't be used so it's lost when the game closes", wallet::key_path().display())
+    };
+    format!("Address: {}\nBalance: {}\nPrivate key: {}\n{}\n\nPaste a private key or a mnemonic and press Enter to import it\nEscape: main menu\n\n{}",
+        backend.gdk.get_address(), balance_text(backend), backend.gdk.get_private_key(), storage, message)
+}
+
+pub fn setup_wallet(mut commands: Commands,game: Res<Game>,backend: Res<Backend>){
+    let font = game.lato_regular_font.clone();
+    commands.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(20.),
+            ..default()
+        },
+        background_color: BackgroundColor::from(MENU_BACKGROUND_COLOR),
+        ..default()
+    })
+    .with_children(|parent| {
+        parent.spawn((Id::new("wallet_text".to_string()), TextBundle::from_section(wallet_text(&backend, ""), TextStyle {
+            font: font.clone(),
+            font_size: 20.,
+            color: Color::rgb(0.9, 0.9, 0.9),
+        })));
+        create_text_input(parent,"key_input",font,1000.);
+    });
+}
+
+// Imports the submitted key or mnemonic, the text shows the new account or what went wrong
+pub fn wallet_input(
+    mut events: EventReader<TextInputSubmitEvent>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut backend: ResMut<Backend>,
+    mut texts: Query<(&Id, &mut Text)>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::MainMenu);
+        return;
+    }
+    for event in events.read() {
+        // The check running is for the account imported before, it would overwrite this one's balance
+        if backend.funding_pending {
+            continue;
+        }
+        let message = match backend.gdk.import_key(&event.value) {
+            // `update_funding` checks the new account and tops it up like at startup
+            Ok(()) => {
+                backend.balance = None;
+                backend.funding_error = None;
+                backend.funding_pending = true;
+                "Key imported".to_string()
+            },
+            Err(error) => format!("{:#}", error)
+        };
+        for (id, mut text) in &mut texts {
+            if id.id == "wallet_text" {
+                text.sections[0].value = wallet_text(&backend, &message);
+            }
+        }
+    }
+}
+
+// Checks and funds an imported account in the background, whichever screen the player goes to meanwhile
+pub fn update_funding(
+    mut backend: ResMut<Backend>,
+    mut texts: Query<(&Id, &mut Text)>,
+    mut task_executor: AsyncTaskRunner<Result<u64>>,
+) {
+    match task_executor.poll() {
+        AsyncTaskStatus::Idle => {
+            if backend.funding_pending {
+                task_executor.start(backend.gdk.ensure_funded());
+            }
+        }
+        AsyncTaskStatus::Pending => (),
+        AsyncTaskStatus::Finished(funding) => {
+            backend.funding_pending = false;
+            match funding {
+                Ok(balance) => backend.balance = Some(balance),
+                Err(error) => backend.funding_error = Some(format!("{:#}", error))
+            }
+            for (id, mut text) in &mut texts {
+                if id.id == "wallet_text" {
+                    text.sections[0].value = wallet_text(&backend, "Key imported");
+                }
+            }
+        }
+    }
+}
+
+pub fn setup_funding_failed(mut commands: Commands,game: Res<Game>,backend: Res<Backend>){
+    let text = format!("Your account {} can't pay for on-chain games\n\n{}\n\nLocal and computer games still work, import a funded key from the Wallet to play on chain\nEscape: main menu",
+        backend.gdk.get_address(), backend.funding_error.as_deref().unwrap_or(""));
+    commands.spawn(NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        background_color: BackgroundColor::from(MENU_BACKGROUND_COLOR),
+        ..default()
+    })
+    .with_children(|parent| {
+        parent.spawn(TextBundle::from_section(text, TextStyle {
+            font: game.lato_regular_font.clone(),
+            font_size: 24.,
+            color: Color::rgb(0.9, 0.5, 0.5),
+        }));
+    });
+}
+
+pub fn funding_failed_input(keys: Res<ButtonInput<KeyCode>>,mut next_state: ResMut<NextState<GameState>>){
+    if keys.just_pressed(KeyCode::Escape) {
+        next_state.set(GameState::MainMenu);
+    }
+}
+
+fn review_text(text: String, font: Handle<Font>) -> TextBundle {
+    TextBundle::from_section(text, TextStyle {
+        font,
+        font_size: REVIEW_FONT_SIZE,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    })
+}
+
+fn summary_text(player: usize, summary: &PlayerSummary) -> String {
+    format!("{}: {:.1} mpt/decision, luck {:+.2}\n{} doubtful, {} errors, {} blunders", player_name(player), summary.error_rate(),
+        summary.luck, summary.doubtful, summary.errors, summary.blunders)
+}
+
+fn decision_label(index: usize, decision: &Decision) -> String {
+    let what = match &decision.kind {
+        DecisionKind::Checker{roll, ..} => format!("{}-{}", roll[0], roll[1]),
+        DecisionKind::NoDouble => "no double".to_string(),
+        DecisionKind::Double => "doubles".to_string(),
+        DecisionKind::Take => "takes".to_string(),
+        DecisionKind::Drop => "passes".to_string()
+    };
+    format!("{}. {} {} {}", index + 1, player_name(decision.player), what, decision.rating.mark())
+}
+
+fn decision_details(decision: &Decision) -> String {
+    let mut lines: Vec<String> = vec![];
+    if let DecisionKind::Checker{played, best, ..} = &decision.kind {
+        lines.push(format!("Played {}", turn_notation(decision.player, played)));
+        lines.push(format!("Best {}", turn_notation(decision.player, best)));
+    }
+    lines.push(format!("Lost {:.3}, {}", decision.equity_loss, decision.rating));
+    if let Some(luck) = decision.luck {
+        lines.push(format!("Luck {:+.3}", luck));
+    }
+    lines.join("\n")
+}
+
+// Both players' error rates, a clickable page of decisions and the details of the selected one
+pub fn build_review_panel(commands: &mut Commands,review: &Review,font: Handle<Font>){
+    let decisions = &review.analysis.decisions;
+    let page_start = review.selected / REVIEW_PAGE_SIZE * REVIEW_PAGE_SIZE;
+    commands.spawn((NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            left: Val::Px(0.),
+            top: Val::Px(0.),
+            width: Val::Px(REVIEW_PANEL_WIDTH),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            row_gap: Val::Px(2.),
+            padding: UiRect::all(Val::Px(5.)),
+            ..default()
+        },
+        background_color: BackgroundColor::from(MENU_BACKGROUND_COLOR),
+        ..default()
+    }, BoardPiece))
+    .with_children(|parent| {
+        for (player, summary) in review.analysis.players.iter().enumerate() {
+            parent.spawn(review_text(summary_text(player, summary), font.clone()));
+        }
+        for (index, decision) in decisions.iter().enumerate().skip(page_start).take(REVIEW_PAGE_SIZE) {
+            let color = if index == review.selected { TEXT_BUTTON_SELECTED_COLOR } else { TEXT_BUTTON_COLOR };
+            parent.spawn((Id::new(format!("review_{}", index)), ButtonBundle {
+                style: Style {
+                    padding: UiRect::horizontal(Val::Px(4.)),
+                    ..default()
+                },
+                background_color: color.into(),
+                ..default()
+            })).with_children(|parent| {
+                parent.spawn(review_text(decision_label(index, decision), font.clone()));
+            });
+        }
+        match decisions.get(review.selected) {
+            Some(decision) => parent.spawn(review_text(decision_details(decision), font.clone())),
+            None => parent.spawn(review_text("No decisions to review".to_string(), font.clone()))
+        };
+        parent.spawn(review_text("Up/Down: select\nEsc: back".to_string(), font.clone()));
+    });
+}
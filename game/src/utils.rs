@@ -1,30 +1,650 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 
-//// GAME CONSTANTS    
+use serde::{Deserialize, Serialize};
+
+use crate::zobrist::{bar_key, board_key, borne_off_key, tower_key};
+
+// GAME CONSTANTS    
 pub const TOWERS_COUNT: usize = 24;
+// Pseudo tower index used by `Move::from` for a nut entering from the bar
+pub const BAR_INDEX: usize = TOWERS_COUNT;
+// Pseudo tower index used by `Move::to` for a nut borne off the board
+pub const OFF_INDEX: usize = TOWERS_COUNT + 1;
+// First tower of a player's home board, in the player's own tower indices
+pub const HOME_TOWER_INDEX: usize = 18;
+pub const NUTS_PER_PLAYER: u8 = 15;
+// Leading byte of `Board::to_bytes`, bumped whenever the layout changes
+pub const BOARD_BYTES_VERSION: u8 = 1;
 
-//// PLAYER CONSTANTS
+// PLAYER CONSTANTS
 pub const PLAYER_HOST: usize = 0;
 pub const PLAYER_GUEST: usize = 1;
 
-#[derive(Copy, Clone)]
+// An empty tower is always `Tower::default()`, the same zeroed tower the contract starts with
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Tower {
     pub nuts: u8,
     pub owner: u8
 }
 
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Bar {
     pub host_nuts: u8,
     pub guest_nuts: u8
 }
 
+impl Bar {
+    pub fn nuts(&self, player: usize) -> u8 {
+        if player == PLAYER_HOST {
+            self.host_nuts
+        }else{
+            self.guest_nuts
+        }
+    }
+
+    pub fn nuts_mut(&mut self, player: usize) -> &mut u8 {
+        if player == PLAYER_HOST {
+            &mut self.host_nuts
+        }else{
+            &mut self.guest_nuts
+        }
+    }
+}
+
+// Nuts each player has already taken off the board
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BorneOff {
+    pub host_nuts: u8,
+    pub guest_nuts: u8
+}
+
+impl BorneOff {
+    pub fn nuts(&self, player: usize) -> u8 {
+        if player == PLAYER_HOST {
+            self.host_nuts
+        }else{
+            self.guest_nuts
+        }
+    }
+
+    pub fn nuts_mut(&mut self, player: usize) -> &mut u8 {
+        if player == PLAYER_HOST {
+            &mut self.host_nuts
+        }else{
+            &mut self.guest_nuts
+        }
+    }
+}
+
 //TODO: simplify the tower to (u8,u8) for player index and num of the player's nuts in the tower
+// Serialized in the layout of the contract's `Board`, borne off nuts are recounted when loading.
+// Boards compare and hash by position; code that edits the public fields directly must call
+// `rehash` afterwards to refresh the Zobrist key that moves keep up to date
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(into = "BoardLayout", try_from = "BoardLayout")]
 pub struct Board {
     pub towers: [Tower;TOWERS_COUNT],  
     pub bar: Bar,		
+    pub borne_off: BorneOff,
+    zobrist: u64,
 }	
 
+impl PartialEq for Board {
+    fn eq(&self, other: &Board) -> bool {
+        // Empty towers are equal whatever owner they were left with
+        let towers_equal = self.towers.iter().zip(other.towers.iter()).all(|(tower, other_tower)| {
+            tower.nuts == other_tower.nuts && (tower.nuts == 0 || tower.owner == other_tower.owner)
+        });
+        towers_equal && self.bar == other.bar && self.borne_off == other.borne_off
+    }
+}
+
+impl Eq for Board {}
+
+impl Hash for Board {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.zobrist());
+    }
+}
+
+// Same fields as the contract's `Board`, a vector of towers followed by the bar,
+// so the BCS bytes of the on-chain resource decode straight into a `Board`
+#[derive(Serialize, Deserialize)]
+struct BoardLayout {
+    towers: Vec<Tower>,
+    bar: Bar
+}
+
+impl From<Board> for BoardLayout {
+    fn from(board: Board) -> BoardLayout {
+        BoardLayout{
+            towers: board.towers.to_vec(),
+            bar: board.bar
+        }
+    }
+}
+
+impl TryFrom<BoardLayout> for Board {
+    type Error = &'static str;
+
+    fn try_from(layout: BoardLayout) -> Result<Board, &'static str> {
+        let towers: [Tower;TOWERS_COUNT] = layout.towers.try_into().map_err(|_| "a board has 24 towers")?;
+        let mut board = Board::empty();
+        board.towers = towers;
+        board.bar = layout.bar;
+        for player in [PLAYER_HOST, PLAYER_GUEST] {
+            *board.borne_off.nuts_mut(player) = NUTS_PER_PLAYER.checked_sub(board.nuts_on_board(player)).ok_or("a player has more than 15 nuts")?;
+        }
+        board.rehash();
+        Ok(board)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BoardViolation {
+    // A player doesn't have exactly 15 nuts across the towers, the bar and borne off
+    NutCount{ player: usize, nuts: u32 },
+    // An empty tower that isn't `Tower::default()`
+    StaleOwner{ tower: usize, owner: u8 },
+    // A tower owned by neither the host nor the guest
+    InvalidOwner{ tower: usize, owner: u8 },
+    TowerOverflow{ tower: usize, nuts: u8 },
+    BarOverflow{ player: usize, nuts: u8 },
+    BorneOffOverflow{ player: usize, nuts: u8 }
+}
+
+impl fmt::Display for BoardViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BoardViolation::NutCount{ player, nuts } => write!(f, "player {} has {} nuts instead of {}", player, nuts, NUTS_PER_PLAYER),
+            BoardViolation::StaleOwner{ tower, owner } => write!(f, "empty tower {} is still owned by player {}", tower, owner),
+            BoardViolation::InvalidOwner{ tower, owner } => write!(f, "tower {} is owned by unknown player {}", tower, owner),
+            BoardViolation::TowerOverflow{ tower, nuts } => write!(f, "tower {} holds {} nuts", tower, nuts),
+            BoardViolation::BarOverflow{ player, nuts } => write!(f, "player {} has {} nuts on the bar", player, nuts),
+            BoardViolation::BorneOffOverflow{ player, nuts } => write!(f, "player {} has borne off {} nuts", player, nuts)
+        }
+    }
+}
+
+// Every invariant a board breaks, see `Board::validate`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BoardError {
+    pub violations: Vec<BoardViolation>
+}
+
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let violations: Vec<String> = self.violations.iter().map(|violation| violation.to_string()).collect();
+        write!(f, "invalid board: {}", violations.join(", "))
+    }
+}
+
+impl std::error::Error for BoardError {}
+
+// A single checker movement, `from` and `to` are global tower indices (or `BAR_INDEX`/`OFF_INDEX`).
+// `hit` records a lone opponent nut sent to the bar, so the move can be undone
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub from: usize,
+    pub to: usize,
+    pub die: u8,
+    pub hit: bool
+}
+
+// The moves a player makes with one roll, in the order they are played
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Turn {
+    pub moves: Vec<Move>
+}
+
+// How a game was won, the loser's position decides how many points it's worth
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResultType {
+    Single,
+    // The loser hasn't borne off any nut
+    Gammon,
+    // A gammon where the loser still has a nut on the bar or in the winner's home board
+    Backgammon
+}
+
+impl ResultType {
+    pub fn points(&self) -> u32 {
+        match self {
+            ResultType::Single => 1,
+            ResultType::Gammon => 2,
+            ResultType::Backgammon => 3
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GameResult {
+    pub winner: usize,
+    pub result_type: ResultType
+}
+
+impl GameResult {
+    pub fn loser(&self) -> usize {
+        opponent(self.winner)
+    }
+
+    pub fn points(&self) -> u32 {
+        self.result_type.points()
+    }
+}
+
 impl Board{
+
+    // A board without any nut, to be filled in and then `rehash`ed
+    pub fn empty() -> Board {
+        Board{
+            towers: [Tower::default();TOWERS_COUNT],
+            bar: Bar::default(),
+            borne_off: BorneOff::default(),
+            zobrist: 0
+        }
+    }
+
+    // Zobrist key of the position, updated in O(1) by every applied or undone move
+    pub fn zobrist(&self) -> u64 {
+        self.zobrist
+    }
+
+    pub fn rehash(&mut self) {
+        self.zobrist = board_key(self);
+    }
+
+    // Checks the board could come out of a real game, listing every broken invariant
+    pub fn validate(&self) -> Result<(), BoardError> {
+        let mut violations: Vec<BoardViolation> = vec![];
+
+        for (tower_index, tower) in self.towers.iter().enumerate() {
+            if tower.nuts == 0 && tower.owner != 0 {
+                violations.push(BoardViolation::StaleOwner{ tower: tower_index, owner: tower.owner });
+            }
+            if tower.nuts > 0 && tower.owner as usize != PLAYER_HOST && tower.owner as usize != PLAYER_GUEST {
+                violations.push(BoardViolation::InvalidOwner{ tower: tower_index, owner: tower.owner });
+            }
+            if tower.nuts > NUTS_PER_PLAYER {
+                violations.push(BoardViolation::TowerOverflow{ tower: tower_index, nuts: tower.nuts });
+            }
+        }
+
+        for player in [PLAYER_HOST, PLAYER_GUEST] {
+            let bar_nuts = self.bar.nuts(player);
+            let borne_off_nuts = self.borne_off.nuts(player);
+            if bar_nuts > NUTS_PER_PLAYER {
+                violations.push(BoardViolation::BarOverflow{ player, nuts: bar_nuts });
+            }
+            if borne_off_nuts > NUTS_PER_PLAYER {
+                violations.push(BoardViolation::BorneOffOverflow{ player, nuts: borne_off_nuts });
+            }
+
+            let tower_nuts: u32 = self.towers.iter()
+                .filter(|tower| tower.owner as usize == player)
+                .map(|tower| tower.nuts as u32)
+                .sum();
+            let nuts = tower_nuts + bar_nuts as u32 + borne_off_nuts as u32;
+            if nuts != NUTS_PER_PLAYER as u32 {
+                violations.push(BoardViolation::NutCount{ player, nuts });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        }else{
+            Err(BoardError{
+                violations
+            })
+        }
+    }
+
+    // Compact form for the network and storage: a version byte followed by the BCS layout
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![BOARD_BYTES_VERSION];
+        bytes.extend(self.to_move_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Board, &'static str> {
+        match bytes.split_first() {
+            Some((&BOARD_BYTES_VERSION, layout)) => Board::from_move_bytes(layout),
+            Some(_) => Err("unsupported board encoding version"),
+            None => Err("empty board encoding")
+        }
+    }
+
+    // BCS bytes of the contract's `Board`: ULEB128 tower count, (nuts, owner) per tower, then the bar
+    pub fn to_move_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![TOWERS_COUNT as u8];
+        for tower in self.towers.iter() {
+            bytes.push(tower.nuts);
+            bytes.push(tower.owner);
+        }
+        bytes.push(self.bar.host_nuts);
+        bytes.push(self.bar.guest_nuts);
+        bytes
+    }
+
+    pub fn from_move_bytes(bytes: &[u8]) -> Result<Board, &'static str> {
+        if bytes.len() != 1 + TOWERS_COUNT * 2 + 2 || bytes[0] as usize != TOWERS_COUNT {
+            return Err("a board has 24 towers and a bar");
+        }
+        let towers = bytes[1..1 + TOWERS_COUNT * 2].chunks(2).map(|tower| Tower{
+            nuts: tower[0],
+            owner: tower[1]
+        }).collect();
+        let bar = Bar{
+            host_nuts: bytes[1 + TOWERS_COUNT * 2],
+            guest_nuts: bytes[2 + TOWERS_COUNT * 2]
+        };
+        Board::try_from(BoardLayout{
+            towers,
+            bar
+        })
+    }
     
+    pub fn nuts_of(&self, player: usize, global_tower_index: usize) -> u8 {
+        let tower = self.towers[global_tower_index];
+        if tower.owner as usize == player {
+            tower.nuts
+        }else{
+            0
+        }
+    }
+
+    // A tower holding two or more of the opponent's nuts can't be landed on
+    pub fn is_blocked(&self, player: usize, global_tower_index: usize) -> bool {
+        let tower = self.towers[global_tower_index];
+        tower.owner as usize != player && tower.nuts >= 2
+    }
+
+    // A blot is a tower with a single nut, which the opponent can hit
+    pub fn is_blot(&self, player: usize, global_tower_index: usize) -> bool {
+        self.nuts_of(player, global_tower_index) == 1
+    }
+
+    /*
+     * Every legal way for `player` to play `dice` (use `roll_to_dice` to expand a roll).
+     * Only the longest sequences are kept, and when just one die of a non-double roll can be
+     * played the higher one must be used. A player who can't move at all gets a single empty turn.
+     */
+    pub fn legal_turns(&self, player: usize, dice: &[u8]) -> Vec<Turn> {
+        // Doubles are generated with non-decreasing source towers, so the same set of
+        // moves isn't listed once per ordering
+        let ordered = dice.len() > 1 && dice.iter().all(|die| *die == dice[0]);
+        let mut turns: Vec<Turn> = vec![];
+        self.collect_turns(player, dice, ordered, 0, &mut vec![], &mut turns);
+
+        let longest = turns.iter().map(|turn| turn.moves.len()).max().unwrap_or(0);
+        turns.retain(|turn| turn.moves.len() == longest);
+
+        if longest == 1 && !ordered {
+            let highest = *dice.iter().max().unwrap();
+            if turns.iter().any(|turn| turn.moves[0].die == highest) {
+                turns.retain(|turn| turn.moves[0].die == highest);
+            }
+        }
+        turns
+    }
+
+    // `legal_turns` without sequences that end in the same position as an earlier one
+    pub fn unique_turns(&self, player: usize, dice: &[u8]) -> Vec<Turn> {
+        let mut seen: HashSet<Board> = HashSet::new();
+        let mut board = self.clone();
+        let mut turns = self.legal_turns(player, dice);
+        turns.retain(|turn| {
+            board.apply_turn(player, turn);
+            let is_new = seen.insert(board.clone());
+            board.undo_turn(player, turn);
+            is_new
+        });
+        turns
+    }
+
+    /*
+     * Moves that can be played next with `dice` while still using as many dice as possible,
+     * so a turn can be entered one move at a time in any order
+     */
+    pub fn legal_moves(&self, player: usize, dice: &[u8]) -> Vec<Move> {
+        let turns = self.legal_turns(player, dice);
+        let longest = turns[0].moves.len();
+        let mut moves: Vec<Move> = vec![];
+
+        for (dice_index, die) in dice.iter().enumerate() {
+            let mut rest = dice.to_vec();
+            rest.remove(dice_index);
+
+            for mv in self.single_moves(player, *die) {
+                if moves.contains(&mv) {
+                    continue;
+                }
+                // With a single playable die the turns already carry the higher die rule
+                if longest == 1 && !turns.iter().any(|turn| turn.moves[0] == mv) {
+                    continue;
+                }
+                let mut next = self.clone();
+                next.apply_move(player, &mv);
+                if next.legal_turns(player, &rest)[0].moves.len() + 1 == longest {
+                    moves.push(mv);
+                }
+            }
+        }
+        moves
+    }
+
+    fn collect_turns(&self, player: usize, dice: &[u8], ordered: bool, min_rank: usize, moves: &mut Vec<Move>, turns: &mut Vec<Turn>) {
+        let mut tried_dice: Vec<u8> = vec![];
+        let mut has_move = false;
+
+        for (dice_index, die) in dice.iter().enumerate() {
+            if tried_dice.contains(die) {
+                continue;
+            }
+            tried_dice.push(*die);
+
+            let mut rest = dice.to_vec();
+            rest.remove(dice_index);
+
+            for mv in self.single_moves(player, *die) {
+                let rank = move_rank(player, mv.from);
+                if ordered && rank < min_rank {
+                    continue;
+                }
+                has_move = true;
+
+                let mut next = self.clone();
+                next.apply_move(player, &mv);
+                moves.push(mv);
+                next.collect_turns(player, &rest, ordered, rank, moves, turns);
+                moves.pop();
+            }
+        }
+
+        if !has_move {
+            turns.push(Turn{
+                moves: moves.clone()
+            });
+        }
+    }
+
+    // A player may bear off once every nut left on the board is in the home board
+    pub fn can_bear_off(&self, player: usize) -> bool {
+        if self.bar.nuts(player) > 0 {
+            return false;
+        }
+        (0..HOME_TOWER_INDEX).all(|player_tower_index| {
+            self.nuts_of(player, player_to_global_tower_index(player, player_tower_index)) == 0
+        })
+    }
+
+    // Nuts `player` still has to bring home and bear off, on the towers and on the bar
+    pub fn nuts_on_board(&self, player: usize) -> u8 {
+        let on_towers: u8 = (0..TOWERS_COUNT).map(|tower_index| self.nuts_of(player, tower_index)).sum();
+        on_towers + self.bar.nuts(player)
+    }
+
+    // The game is over once a player has borne off all their nuts. A board missing nuts, like `Board::empty()`, has no winner
+    pub fn result(&self) -> Option<GameResult> {
+        let winner = [PLAYER_HOST, PLAYER_GUEST].into_iter().find(|player| self.borne_off.nuts(*player) == NUTS_PER_PLAYER)?;
+        let loser = opponent(winner);
+
+        let result_type = if self.borne_off.nuts(loser) > 0 {
+            ResultType::Single
+        }else if self.bar.nuts(loser) > 0 || self.has_nuts_in_home(loser, winner) {
+            ResultType::Backgammon
+        }else{
+            ResultType::Gammon
+        };
+
+        Some(GameResult{
+            winner,
+            result_type
+        })
+    }
+
+    // Whether `player` has any nut inside `home_player`'s home board
+    fn has_nuts_in_home(&self, player: usize, home_player: usize) -> bool {
+        (HOME_TOWER_INDEX..TOWERS_COUNT).any(|player_tower_index| {
+            self.nuts_of(player, player_to_global_tower_index(home_player, player_tower_index)) > 0
+        })
+    }
+
+    // Moves `player` can make with a single die, ignoring the rest of the roll
+    fn single_moves(&self, player: usize, die: u8) -> Vec<Move> {
+        let mut moves: Vec<Move> = vec![];
+
+        // Nuts on the bar have to enter before anything else moves
+        if self.bar.nuts(player) > 0 {
+            let to = player_to_global_tower_index(player, die as usize - 1);
+            if !self.is_blocked(player, to) {
+                moves.push(Move{
+                    from: BAR_INDEX,
+                    to,
+                    die,
+                    hit: self.is_blot(opponent(player), to)
+                });
+            }
+            return moves;
+        }
+
+        let can_bear_off = self.can_bear_off(player);
+        let mut has_higher_nuts = false;
+
+        for player_tower_index in 0..TOWERS_COUNT {
+            let from = player_to_global_tower_index(player, player_tower_index);
+            if self.nuts_of(player, from) == 0 {
+                continue;
+            }
+            let target = player_tower_index + die as usize;
+            if target < TOWERS_COUNT {
+                let to = player_to_global_tower_index(player, target);
+                if !self.is_blocked(player, to) {
+                    moves.push(Move{
+                        from,
+                        to,
+                        die,
+                        hit: self.is_blot(opponent(player), to)
+                    });
+                }
+            }else if can_bear_off && (target == TOWERS_COUNT || !has_higher_nuts) {
+                // An exact die bears off from its tower, a higher die only from the highest occupied one
+                moves.push(Move{
+                    from,
+                    to: OFF_INDEX,
+                    die,
+                    hit: false
+                });
+            }
+            has_higher_nuts = true;
+        }
+        moves
+    }
+
+    pub fn apply_move(&mut self, player: usize, mv: &Move) {
+        if mv.from == BAR_INDEX {
+            self.set_bar(player, self.bar.nuts(player) - 1);
+        }else{
+            self.take_nut(mv.from);
+        }
+
+        if mv.to == OFF_INDEX {
+            self.set_borne_off(player, self.borne_off.nuts(player) + 1);
+            return;
+        }
+        if mv.hit {
+            self.take_nut(mv.to);
+            self.set_bar(opponent(player), self.bar.nuts(opponent(player)) + 1);
+        }
+        self.put_nut(player, mv.to);
+    }
+
+    pub fn undo_move(&mut self, player: usize, mv: &Move) {
+        if mv.to == OFF_INDEX {
+            self.set_borne_off(player, self.borne_off.nuts(player) - 1);
+        }else{
+            self.take_nut(mv.to);
+        }
+        if mv.hit {
+            self.set_bar(opponent(player), self.bar.nuts(opponent(player)) - 1);
+            self.put_nut(opponent(player), mv.to);
+        }
+
+        if mv.from == BAR_INDEX {
+            self.set_bar(player, self.bar.nuts(player) + 1);
+        }else{
+            self.put_nut(player, mv.from);
+        }
+    }
+
+    pub fn apply_turn(&mut self, player: usize, turn: &Turn) {
+        for mv in turn.moves.iter() {
+            self.apply_move(player, mv);
+        }
+    }
+
+    pub fn undo_turn(&mut self, player: usize, turn: &Turn) {
+        for mv in turn.moves.iter().rev() {
+            self.undo_move(player, mv);
+        }
+    }
+
+    fn take_nut(&mut self, global_tower_index: usize) {
+        let tower = &mut self.towers[global_tower_index];
+        self.zobrist ^= tower_key(global_tower_index, tower.owner, tower.nuts) ^ tower_key(global_tower_index, tower.owner, tower.nuts - 1);
+        tower.nuts -= 1;
+        if tower.nuts == 0 {
+            *tower = Tower::default();
+        }
+    }
+
+    fn put_nut(&mut self, player: usize, global_tower_index: usize) {
+        let tower = &mut self.towers[global_tower_index];
+        self.zobrist ^= tower_key(global_tower_index, tower.owner, tower.nuts) ^ tower_key(global_tower_index, player as u8, tower.nuts + 1);
+        tower.nuts += 1;
+        tower.owner = player as u8;
+    }
+
+    fn set_bar(&mut self, player: usize, nuts: u8) {
+        self.zobrist ^= bar_key(player, self.bar.nuts(player)) ^ bar_key(player, nuts);
+        *self.bar.nuts_mut(player) = nuts;
+    }
+
+    fn set_borne_off(&mut self, player: usize, nuts: u8) {
+        self.zobrist ^= borne_off_key(player, self.borne_off.nuts(player)) ^ borne_off_key(player, nuts);
+        *self.borne_off.nuts_mut(player) = nuts;
+    }
+}
+
+// Position of a move's source in the player's direction of travel, the bar comes first
+fn move_rank(player: usize, from: usize) -> usize {
+    if from == BAR_INDEX {
+        0
+    }else{
+        to_player_tower_index(player, from) + 1
+    }
 }
 
 pub fn initialize() -> Board{
@@ -33,7 +653,7 @@ pub fn initialize() -> Board{
         guest_nuts: 0,
         host_nuts: 0
     };
-    let mut towers:[Tower;TOWERS_COUNT] = [Tower{nuts:0,owner:PLAYER_GUEST as u8};TOWERS_COUNT];
+    let mut towers:[Tower;TOWERS_COUNT] = [Tower::default();TOWERS_COUNT];
 
     let tower_ids = [1,12,17,19];
     let tower_nuts = [2,5,3,5];
@@ -49,14 +669,53 @@ pub fn initialize() -> Board{
             };
         }
     }
-    let board = Board{
-        bar: bar,
-        towers: towers
+    let mut board = Board{
+        bar,
+        towers,
+        borne_off: BorneOff::default(),
+        zobrist: 0
     };
+    board.rehash();
 
     board
 }
 
+pub fn opponent(player: usize) -> usize {
+    if player == PLAYER_HOST {
+        PLAYER_GUEST
+    }else{
+        PLAYER_HOST
+    }
+}
+
+// Dice to play for a roll, doubles are played four times
+pub fn roll_to_dice(roll: [u8;2]) -> Vec<u8> {
+    if roll[0] == roll[1] {
+        vec![roll[0];4]
+    }else{
+        roll.to_vec()
+    }
+}
+
+// Standard notation seen by `player`, points numbered from 24 down to their ace point, e.g. "bar/22" or "8/5*"
+pub fn move_notation(player: usize, mv: &Move) -> String {
+    let point = |global_tower_index: usize| (TOWERS_COUNT - to_player_tower_index(player, global_tower_index)).to_string();
+    let from = if mv.from == BAR_INDEX { "bar".to_string() } else { point(mv.from) };
+    let to = if mv.to == OFF_INDEX { "off".to_string() } else { point(mv.to) };
+    format!("{}/{}{}", from, to, if mv.hit { "*" } else { "" })
+}
+
+pub fn turn_notation(player: usize, turn: &Turn) -> String {
+    if turn.moves.is_empty() {
+        return "no move".to_string();
+    }
+    turn.moves.iter().map(|mv| move_notation(player, mv)).collect::<Vec<String>>().join(" ")
+}
+
+/*
+ * Player tower indices run in the player's direction of travel, from 0 for their 24 point where the back
+ * nuts start to 23 for their ace point. The host's are the global indices, the guest's run the other way
+ */
 pub fn player_to_global_tower_index(player:usize, tower_index: usize)-> usize{
     if player == PLAYER_HOST {
         tower_index
@@ -65,11 +724,316 @@ pub fn player_to_global_tower_index(player:usize, tower_index: usize)-> usize{
     }        
 }
 
-pub fn global_to_player_tower_index(player:usize, tower_index: usize)-> usize{
+// Inverse of `player_to_global_tower_index`. Not the contract's `global_to_player_tower_index`, which counts
+// from the player's ace point instead
+pub fn to_player_tower_index(player:usize, tower_index: usize)-> usize{
     if player == PLAYER_HOST {
-        TOWERS_COUNT - tower_index - 1
-    }else{
         tower_index
+    }else{
+        TOWERS_COUNT - tower_index - 1
     }            
 }
 
+// https://www.bkgm.com/rules.html
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    // A board from (point, nuts) pairs numbered from each owner's side, 25 being the bar.
+    // Nuts not listed are borne off
+    fn board(host: &[(usize, u8)], guest: &[(usize, u8)]) -> Board {
+        let mut towers = vec![Tower::default(); TOWERS_COUNT];
+        let mut bar = Bar::default();
+        for (player, points) in [(PLAYER_HOST, host), (PLAYER_GUEST, guest)] {
+            for (point, nuts) in points {
+                if *point == TOWERS_COUNT + 1 {
+                    *bar.nuts_mut(player) += nuts;
+                    continue;
+                }
+                towers[player_to_global_tower_index(player, TOWERS_COUNT - point)] = Tower{
+                    nuts: *nuts,
+                    owner: player as u8
+                };
+            }
+        }
+        let board = Board::try_from(BoardLayout{
+            towers,
+            bar
+        }).unwrap();
+        assert_eq!(board.validate(), Ok(()));
+        board
+    }
+
+    // Every legal turn in notation, the moves of a turn and the turns sorted so the order played doesn't matter
+    fn turns(board: &Board, player: usize, dice: &[u8]) -> Vec<String> {
+        let mut turns: Vec<String> = board.legal_turns(player, dice).iter().map(|turn| {
+            let mut moves: Vec<String> = turn.moves.iter().map(|mv| move_notation(player, mv)).collect();
+            moves.sort();
+            moves.join(" ")
+        }).collect();
+        turns.sort();
+        turns
+    }
+
+    #[test]
+    fn higher_die_is_played_when_only_one_can_be() {
+        let board = board(&[(24, 1)], &[(12, 2), (24, 13)]);
+        assert_eq!(turns(&board, PLAYER_HOST, &[6, 5]), vec!["24/18"]);
+        assert_eq!(turns(&board, PLAYER_HOST, &[5, 6]), vec!["24/18"]);
+    }
+
+    #[test]
+    fn nuts_on_the_bar_enter_first() {
+        let board = board(&[(25, 1), (6, 14)], &[(24, 15)]);
+        let legal_turns = board.legal_turns(PLAYER_HOST, &[6, 1]);
+        assert!(!legal_turns.is_empty());
+        assert!(legal_turns.iter().all(|turn| turn.moves[0].from == BAR_INDEX));
+
+        // With both entry points closed nothing else may move
+        let board = self::board(&[(25, 1), (6, 14)], &[(1, 2), (6, 2), (2, 11)]);
+        assert_eq!(board.legal_turns(PLAYER_HOST, &[6, 1]), vec![Turn::default()]);
+    }
+
+    #[test]
+    fn both_dice_are_played_when_possible() {
+        // 10/4 is blocked and 3/1 leaves the 6 unplayable, only 10/8/2 uses both dice
+        let board = board(&[(10, 1), (3, 1)], &[(21, 2), (24, 13)]);
+        assert_eq!(turns(&board, PLAYER_HOST, &[6, 2]), vec!["10/8 8/2"]);
+    }
+
+    #[test]
+    fn doubles_list_each_set_of_moves_once() {
+        let board = board(&[(24, 2)], &[(24, 15)]);
+        let turns = turns(&board, PLAYER_HOST, &roll_to_dice([1, 1]));
+        assert_eq!(turns, vec!["21/20 22/21 23/22 24/23", "22/21 23/22 24/23 24/23", "23/22 23/22 24/23 24/23"]);
+
+        let start = initialize();
+        let turns = self::turns(&start, PLAYER_HOST, &roll_to_dice([2, 2]));
+        let unique: HashSet<&String> = turns.iter().collect();
+        assert_eq!(unique.len(), turns.len());
+    }
+
+    // Global index of a point numbered from `player`'s side, 25 being the bar
+    fn point(player: usize, point: usize) -> usize {
+        if point == TOWERS_COUNT + 1 {
+            BAR_INDEX
+        }else{
+            player_to_global_tower_index(player, TOWERS_COUNT - point)
+        }
+    }
+
+    // Undoing must give back the very same towers, bar, borne off nuts and key, not just an equal position
+    fn assert_restored(board: &Board, original: &Board) {
+        assert_eq!(board.towers, original.towers);
+        assert_eq!(board.bar, original.bar);
+        assert_eq!(board.borne_off, original.borne_off);
+        assert_eq!(board.zobrist, original.zobrist);
+    }
+
+    // A move from `from` to `to` in the moving player's point numbers, 25 being the bar and 0 off the board
+    struct MoveCase {
+        player: usize,
+        before: [&'static [(usize, u8)]; 2],
+        from: usize,
+        to: usize,
+        die: u8,
+        hit: bool,
+        after: [&'static [(usize, u8)]; 2]
+    }
+
+    #[test]
+    fn moves_apply_and_undo() {
+        let cases = [
+            MoveCase{ player: PLAYER_HOST, before: [&[(13, 2), (6, 13)], &[(24, 15)]], from: 13, to: 7, die: 6, hit: false, after: [&[(13, 1), (7, 1), (6, 13)], &[(24, 15)]] },
+            MoveCase{ player: PLAYER_HOST, before: [&[(13, 2), (6, 13)], &[(18, 1), (24, 14)]], from: 13, to: 7, die: 6, hit: true, after: [&[(13, 1), (7, 1), (6, 13)], &[(25, 1), (24, 14)]] },
+            MoveCase{ player: PLAYER_HOST, before: [&[(25, 1), (6, 14)], &[(24, 15)]], from: 25, to: 22, die: 3, hit: false, after: [&[(22, 1), (6, 14)], &[(24, 15)]] },
+            MoveCase{ player: PLAYER_HOST, before: [&[(25, 1), (6, 14)], &[(3, 1), (24, 14)]], from: 25, to: 22, die: 3, hit: true, after: [&[(22, 1), (6, 14)], &[(25, 1), (24, 14)]] },
+            MoveCase{ player: PLAYER_GUEST, before: [&[(24, 15)], &[(25, 2), (6, 13)]], from: 25, to: 20, die: 5, hit: false, after: [&[(24, 15)], &[(25, 1), (20, 1), (6, 13)]] },
+            MoveCase{ player: PLAYER_HOST, before: [&[(6, 1), (5, 14)], &[(24, 15)]], from: 6, to: 0, die: 6, hit: false, after: [&[(5, 14)], &[(24, 15)]] }
+        ];
+        for case in cases {
+            let original = board(case.before[PLAYER_HOST], case.before[PLAYER_GUEST]);
+            let mv = Move{
+                from: point(case.player, case.from),
+                to: if case.to == 0 { OFF_INDEX } else { point(case.player, case.to) },
+                die: case.die,
+                hit: case.hit
+            };
+            let notation = move_notation(case.player, &mv);
+            assert!(original.legal_moves(case.player, &[case.die]).contains(&mv), "{} isn't legal", notation);
+
+            let mut board = original.clone();
+            board.apply_move(case.player, &mv);
+            assert_eq!(board, self::board(case.after[PLAYER_HOST], case.after[PLAYER_GUEST]), "after {}", notation);
+            assert_eq!(board.zobrist, board_key(&board));
+
+            board.undo_move(case.player, &mv);
+            assert_restored(&board, &original);
+        }
+    }
+
+    #[test]
+    fn undoing_a_turn_restores_the_board() {
+        let start = initialize();
+        for player in [PLAYER_HOST, PLAYER_GUEST] {
+            for roll in [[6, 5], [4, 4], [2, 1]] {
+                for turn in start.legal_turns(player, &roll_to_dice(roll)) {
+                    let mut board = start.clone();
+                    board.apply_turn(player, &turn);
+                    assert_eq!(board.validate(), Ok(()));
+                    board.undo_turn(player, &turn);
+                    assert_restored(&board, &start);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn results_score_single_gammon_and_backgammon() {
+        let result = |host: &[(usize, u8)], guest: &[(usize, u8)]| board(host, guest).result();
+        let host_wins = |result_type| Some(GameResult{ winner: PLAYER_HOST, result_type });
+
+        assert_eq!(result(&[(2, 1)], &[(24, 15)]), None);
+        assert_eq!(result(&[], &[(24, 14)]), host_wins(ResultType::Single));
+        assert_eq!(result(&[], &[(12, 15)]), host_wins(ResultType::Gammon));
+        // A nut left in the winner's home board, the loser's 19 to 24 points, or on the bar
+        assert_eq!(result(&[], &[(19, 1), (12, 14)]), host_wins(ResultType::Backgammon));
+        assert_eq!(result(&[], &[(25, 1), (12, 14)]), host_wins(ResultType::Backgammon));
+        assert_eq!(result(&[(12, 15)], &[]), Some(GameResult{ winner: PLAYER_GUEST, result_type: ResultType::Gammon }));
+    }
+
+    #[test]
+    fn boards_without_every_nut_have_no_result() {
+        assert_eq!(Board::empty().result(), None);
+        assert_eq!(initialize().result(), None);
+    }
+
+    #[test]
+    fn boards_round_trip_through_serde_and_bytes() {
+        let mut board = initialize();
+        let turn = board.legal_turns(PLAYER_HOST, &[6, 4]).remove(0);
+        board.apply_turn(PLAYER_HOST, &turn);
+        let mid_game = self::board(&[(25, 1), (13, 4), (6, 5)], &[(25, 2), (1, 1), (8, 3)]);
+
+        for board in [initialize(), board, mid_game] {
+            // The hand written layout is the contract's BCS encoding
+            assert_eq!(board.to_move_bytes(), bcs::to_bytes(&board).unwrap());
+            assert_restored(&bcs::from_bytes(&board.to_move_bytes()).unwrap(), &board);
+            assert_restored(&Board::from_bytes(&board.to_bytes()).unwrap(), &board);
+            assert_restored(&serde_json::from_str(&serde_json::to_string(&board).unwrap()).unwrap(), &board);
+        }
+        assert!(Board::from_bytes(&[BOARD_BYTES_VERSION + 1]).is_err());
+    }
+
+    fn violations(board: &Board) -> Vec<BoardViolation> {
+        board.validate().map_or_else(|error| error.violations, |()| vec![])
+    }
+
+    #[test]
+    fn validate_lists_every_broken_invariant() {
+        assert_eq!(violations(&initialize()), vec![]);
+
+        let mut board = initialize();
+        board.towers[0].nuts -= 1;
+        assert_eq!(violations(&board), vec![BoardViolation::NutCount{ player: PLAYER_HOST, nuts: 14 }]);
+
+        // A tower has a single owner, so the host's nut landing on the guest's 6 point takes its five nuts over
+        let mut board = initialize();
+        let guest_point = player_to_global_tower_index(PLAYER_GUEST, 18);
+        assert_eq!(board.towers[guest_point], Tower{ nuts: 5, owner: PLAYER_GUEST as u8 });
+        board.towers[0].nuts -= 1;
+        board.towers[guest_point] = Tower{ nuts: 6, owner: PLAYER_HOST as u8 };
+        assert_eq!(violations(&board), vec![
+            BoardViolation::NutCount{ player: PLAYER_HOST, nuts: 20 },
+            BoardViolation::NutCount{ player: PLAYER_GUEST, nuts: 10 }
+        ]);
+
+        let mut board = initialize();
+        board.bar.guest_nuts = 16;
+        assert_eq!(violations(&board), vec![
+            BoardViolation::BarOverflow{ player: PLAYER_GUEST, nuts: 16 },
+            BoardViolation::NutCount{ player: PLAYER_GUEST, nuts: 31 }
+        ]);
+
+        let mut board = initialize();
+        board.towers[2].owner = PLAYER_GUEST as u8;
+        board.towers[0].owner = 7;
+        board.borne_off.host_nuts = 16;
+        assert_eq!(violations(&board), vec![
+            BoardViolation::InvalidOwner{ tower: 0, owner: 7 },
+            BoardViolation::StaleOwner{ tower: 2, owner: PLAYER_GUEST as u8 },
+            BoardViolation::BorneOffOverflow{ player: PLAYER_HOST, nuts: 16 },
+            BoardViolation::NutCount{ player: PLAYER_HOST, nuts: 29 }
+        ]);
+    }
+
+    #[test]
+    fn incremental_zobrist_keys_match_the_full_hash() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut board = initialize();
+        let mut player = PLAYER_HOST;
+        while board.result().is_none() {
+            let dice = roll_to_dice([rng.gen_range(1..=6), rng.gen_range(1..=6)]);
+            for mv in board.legal_moves(player, &dice) {
+                let mut next = board.clone();
+                next.apply_move(player, &mv);
+                assert_eq!(next.zobrist, board_key(&next), "after {}", move_notation(player, &mv));
+                next.undo_move(player, &mv);
+                assert_eq!(next.zobrist, board_key(&next), "after undoing {}", move_notation(player, &mv));
+            }
+            let turns = board.legal_turns(player, &dice);
+            board.apply_turn(player, &turns[rng.gen_range(0..turns.len())]);
+            player = opponent(player);
+        }
+    }
+
+    #[test]
+    fn player_tower_indices_round_trip() {
+        for player in [PLAYER_HOST, PLAYER_GUEST] {
+            for tower_index in 0..TOWERS_COUNT {
+                assert_eq!(to_player_tower_index(player, player_to_global_tower_index(player, tower_index)), tower_index);
+                assert_eq!(player_to_global_tower_index(player, to_player_tower_index(player, tower_index)), tower_index);
+            }
+        }
+        // Both players start with two nuts on their 24 point, index 0 in their direction of travel
+        let start = initialize();
+        assert_eq!(start.nuts_of(PLAYER_HOST, player_to_global_tower_index(PLAYER_HOST, 0)), 2);
+        assert_eq!(start.nuts_of(PLAYER_GUEST, player_to_global_tower_index(PLAYER_GUEST, 0)), 2);
+        assert_eq!(to_player_tower_index(PLAYER_GUEST, 0), TOWERS_COUNT - 1);
+    }
+
+    #[test]
+    fn higher_die_bears_off_only_from_the_highest_point() {
+        let board = board(&[(4, 1), (2, 1)], &[(24, 15)]);
+        assert_eq!(turns(&board, PLAYER_HOST, &[6]), vec!["4/off"]);
+        assert!(turns(&board, PLAYER_HOST, &[6, 1]).iter().all(|turn| !turn.contains("2/off")));
+    }
+
+    #[test]
+    fn bearing_off_needs_every_nut_home() {
+        let board = board(&[(7, 1), (2, 14)], &[(24, 15)]);
+        assert!(!board.can_bear_off(PLAYER_HOST));
+        assert_eq!(turns(&board, PLAYER_HOST, &[2]), vec!["7/5"]);
+        // Once the last nut comes home the second die may bear off
+        assert!(turns(&board, PLAYER_HOST, &[1, 2]).contains(&"2/off 7/6".to_string()));
+
+        let board = self::board(&[(25, 1), (2, 14)], &[(24, 15)]);
+        assert!(!board.can_bear_off(PLAYER_HOST));
+    }
+
+    #[test]
+    fn higher_die_bears_off_while_no_higher_point_is_occupied() {
+        // A 5 can't bear off from the 3 point with a nut still on the 6 point, it has to move 6/1
+        let board = board(&[(6, 1), (3, 14)], &[(12, 15)]);
+        assert_eq!(turns(&board, PLAYER_HOST, &[5]), vec!["6/1"]);
+        // Once the 6 point is cleared, by either die, the other one bears off from the 3 point
+        assert_eq!(turns(&board, PLAYER_HOST, &[6, 5]), vec!["3/off 6/1", "3/off 6/off"]);
+
+        let board = self::board(&[(24, 15)], &[(3, 2), (1, 13)]);
+        assert_eq!(turns(&board, PLAYER_GUEST, &[4]), vec!["3/off"]);
+        assert_eq!(turns(&board, PLAYER_GUEST, &[1]), vec!["1/off", "3/2"]);
+    }
+}
@@ -0,0 +1,113 @@
+use std::{env, fs, io::Write, path::{Path, PathBuf}};
+
+use anyhow::{bail, Context, Result};
+use aptos_sdk::crypto::ed25519::Ed25519PrivateKey;
+use aptos_sdk::crypto::ValidCryptoMaterialStringExt;
+use aptos_sdk::types::{AccountKey, LocalAccount};
+use once_cell::sync::Lazy;
+use rand::rngs::OsRng;
+
+// The player's account is kept between launches in a key file holding its private key as "0x" hex,
+// so a hosted game can be found again. It lives in the user's config directory, wherever the game is
+// launched from, and APTOS_KEY_FILE moves it, e.g. to play two accounts on one machine
+static KEY_PATH: Lazy<PathBuf> = Lazy::new(|| {
+    match env::var_os("APTOS_KEY_FILE") {
+        Some(path) => PathBuf::from(path),
+        // Without a config directory it falls back to the git ignored wallet/ of the working directory
+        None => config_dir().map_or(PathBuf::from(LEGACY_KEY_PATH), |dir| dir.join(APP_DIRECTORY).join("player.key"))
+    }
+});
+
+const APP_DIRECTORY: &str = "aptos-backgammon";
+const LEGACY_KEY_PATH: &str = "wallet/player.key";
+
+// Path the Aptos wallets derive their first account from, BIP-44 with Aptos' coin type 637
+const DERIVATION_PATH: &str = "m/44'/637'/0'/0'/0'";
+const MNEMONIC_WORD_COUNTS: [usize;5] = [12, 15, 18, 21, 24];
+
+pub fn key_path() -> &'static Path {
+    KEY_PATH.as_path()
+}
+
+// $XDG_CONFIG_HOME or ~/.config on unix, %APPDATA% on Windows
+fn config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("APPDATA").map(PathBuf::from);
+    }
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+// The account of the key file, a new one saved there on the first launch
+pub fn load_or_create() -> Result<LocalAccount> {
+    if key_path().exists() {
+        return read_key(key_path());
+    }
+    // Keys used to be saved in the working directory, the first launch moves them to the config directory
+    let legacy_path = Path::new(LEGACY_KEY_PATH);
+    if legacy_path.exists() && legacy_path != key_path() {
+        let account = read_key(legacy_path)?;
+        save(&account)?;
+        fs::remove_file(legacy_path)?;
+        return Ok(account);
+    }
+    let account = LocalAccount::generate(&mut OsRng);
+    save(&account)?;
+    Ok(account)
+}
+
+// An account from a pasted "0x" hex private key or a BIP-39 mnemonic, saved as the player's key
+pub fn import(text: &str) -> Result<LocalAccount> {
+    let text = text.trim();
+    let words = text.split_whitespace().count();
+    let account = if MNEMONIC_WORD_COUNTS.contains(&words) {
+        let phrase = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        LocalAccount::from_derive_path(DERIVATION_PATH, &phrase, 0).context("Invalid mnemonic")?
+    } else if words == 1 {
+        account_from_private_key(text)?
+    } else {
+        bail!("Expected a private key or a mnemonic of 12 to 24 words");
+    };
+    save(&account)?;
+    Ok(account)
+}
+
+pub fn save(account: &LocalAccount) -> Result<()> {
+    if let Some(parent) = key_path().parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_private(key_path(), &private_key_hex(account)).with_context(|| format!("Failed to write {}", key_path().display()))
+}
+
+// Only the player may read the key
+#[cfg(unix)]
+fn write_private(path: &Path, text: &str) -> std::io::Result<()> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mut file = fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    // `mode` only applies to new files, a key file written by an older version is tightened too
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(text.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, text: &str) -> std::io::Result<()> {
+    fs::File::create(path)?.write_all(text.as_bytes())
+}
+
+pub fn private_key_hex(account: &LocalAccount) -> String {
+    account.private_key().to_encoded_string().unwrap()
+}
+
+fn read_key(path: &Path) -> Result<LocalAccount> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    account_from_private_key(text.trim()).with_context(|| format!("Invalid key in {}", path.display()))
+}
+
+fn account_from_private_key(hex: &str) -> Result<LocalAccount> {
+    let private_key = Ed25519PrivateKey::from_encoded_string(hex).context("Invalid private key")?;
+    let key = AccountKey::from_private_key(private_key);
+    // Sequence numbers are fetched from the chain before each transaction
+    Ok(LocalAccount::new(key.authentication_key().account_address(), key, 0))
+}
@@ -0,0 +1,111 @@
+use once_cell::sync::Lazy;
+
+use crate::utils::{Board, NUTS_PER_PLAYER, PLAYER_GUEST, PLAYER_HOST, TOWERS_COUNT};
+
+// Random keys XORed together for every piece of a position, so a move only touches a few of them
+// https://en.wikipedia.org/wiki/Zobrist_hashing
+
+const COUNTS: usize = NUTS_PER_PLAYER as usize + 1;
+// Any die value is left at most four times, on doubles
+const DICE_COUNTS: usize = 5;
+// Cube values 1 to 64
+const CUBE_VALUES: usize = 7;
+
+pub struct ZobristKeys {
+    towers: [[[u64;COUNTS];2];TOWERS_COUNT],
+    bar: [[u64;COUNTS];2],
+    borne_off: [[u64;COUNTS];2],
+    pub side_to_move: u64,
+    pub rolled: u64,
+    dice: [[u64;DICE_COUNTS];7],
+    cube_value: [u64;CUBE_VALUES],
+    // Host, guest and centered
+    cube_owner: [u64;3]
+}
+
+// Fixed seed, keys are the same on every run so they can be stored next to cached evaluations
+pub static KEYS: Lazy<ZobristKeys> = Lazy::new(|| {
+    let mut state: u64 = 0x6261_636b_6761_6d6d;
+    let mut next = || {
+        // splitmix64
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+
+    let mut keys = ZobristKeys{
+        towers: [[[0;COUNTS];2];TOWERS_COUNT],
+        bar: [[0;COUNTS];2],
+        borne_off: [[0;COUNTS];2],
+        side_to_move: 0,
+        rolled: 0,
+        dice: [[0;DICE_COUNTS];7],
+        cube_value: [0;CUBE_VALUES],
+        cube_owner: [0;3]
+    };
+    // A count of zero keeps a zero key, empty towers don't depend on their stale owner
+    for tower in keys.towers.iter_mut() {
+        for player_keys in tower.iter_mut() {
+            for key in player_keys[1..].iter_mut() {
+                *key = next();
+            }
+        }
+    }
+    for player in [PLAYER_HOST, PLAYER_GUEST] {
+        for count in 1..COUNTS {
+            keys.bar[player][count] = next();
+            keys.borne_off[player][count] = next();
+        }
+    }
+    keys.side_to_move = next();
+    keys.rolled = next();
+    for die_keys in keys.dice[1..].iter_mut() {
+        for key in die_keys[1..].iter_mut() {
+            *key = next();
+        }
+    }
+    for key in keys.cube_value.iter_mut().chain(keys.cube_owner.iter_mut()) {
+        *key = next();
+    }
+    keys
+});
+
+pub fn tower_key(global_tower_index: usize, owner: u8, nuts: u8) -> u64 {
+    KEYS.towers[global_tower_index][(owner as usize).min(1)][(nuts as usize).min(COUNTS - 1)]
+}
+
+pub fn bar_key(player: usize, nuts: u8) -> u64 {
+    KEYS.bar[player][(nuts as usize).min(COUNTS - 1)]
+}
+
+pub fn borne_off_key(player: usize, nuts: u8) -> u64 {
+    KEYS.borne_off[player][(nuts as usize).min(COUNTS - 1)]
+}
+
+// Key of the dice still to play, which only depends on how many of each value are left
+pub fn dice_key(dice: &[u8]) -> u64 {
+    (1..=6).fold(0, |key, value| {
+        let count = dice.iter().filter(|die| **die == value).count();
+        key ^ KEYS.dice[value as usize][count.min(DICE_COUNTS - 1)]
+    })
+}
+
+pub fn cube_key(value: u32, owner: Option<usize>) -> u64 {
+    let value_index = (value.trailing_zeros() as usize).min(CUBE_VALUES - 1);
+    KEYS.cube_value[value_index] ^ KEYS.cube_owner[owner.unwrap_or(2).min(2)]
+}
+
+// Full key of a board, `Board` keeps it up to date incrementally as moves are applied
+pub fn board_key(board: &Board) -> u64 {
+    let mut key: u64 = 0;
+    for (tower_index, tower) in board.towers.iter().enumerate() {
+        key ^= tower_key(tower_index, tower.owner, tower.nuts);
+    }
+    for player in [PLAYER_HOST, PLAYER_GUEST] {
+        key ^= bar_key(player, board.bar.nuts(player));
+        key ^= borne_off_key(player, board.borne_off.nuts(player));
+    }
+    key
+}